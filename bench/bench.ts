@@ -43,13 +43,16 @@ await $`cargo build --release`;
 
 const htmdCmd =
   "cargo run --release -- ./bench/bench-pages -o ./bench/bench-out/htmd";
+// Single worker run, compare it with the default (one worker per CPU) to see the effect of --jobs
+const htmdSingleJobCmd =
+  "cargo run --release -- ./bench/bench-pages -o ./bench/bench-out/htmd-j1 -j 1";
 const turndownCmd = "bun bench/turndownCli.ts ./bench/bench-pages";
 const pandocCmd = "bun bench/pandocBatchCli.ts ./bench/bench-pages";
 
 console.log("Benchmarking...");
 
 const output =
-  await $`hyperfine --warmup 3 --runs 5 '${htmdCmd}' '${turndownCmd}' '${pandocCmd}'`.text();
+  await $`hyperfine --warmup 3 --runs 5 '${htmdCmd}' '${htmdSingleJobCmd}' '${turndownCmd}' '${pandocCmd}'`.text();
 
 console.log(`Output:\n${output}`);
 
@@ -95,7 +98,7 @@ async function turndownVersion(): Promise<string> {
 }
 
 async function writeBenchmarkResultReadme(result: string) {
-  const benchCommand = `hyperfine --warmup 3 --runs 5 \\\n'${htmdCmd}' \\\n'${turndownCmd}' \\\n'${pandocCmd}'`;
+  const benchCommand = `hyperfine --warmup 3 --runs 5 \\\n'${htmdCmd}' \\\n'${htmdSingleJobCmd}' \\\n'${turndownCmd}' \\\n'${pandocCmd}'`;
   const inputFilesInfo = await getInputFilesInfo();
   const md = `# Benchmark
 
@@ -0,0 +1,308 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Config,
+    index_util::{html_title, page_title, Page},
+    io_util::{read_html_file, write_markdown, InputLimit},
+    layout_util::{plan_layout, rewrite_page_links, Layout, LayoutEntry},
+    limit_util::{ConvertLimits, Failure},
+    meta_util::PageConverter,
+    path_util::{common_ancestor, normalize_path},
+    pool_util::run_parallel,
+};
+
+/// An input file and the Markdown file it is converted to.
+#[derive(Debug, PartialEq)]
+pub struct PlanEntry {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// A finished file, passed to the progress callback.
+pub struct FileEvent<'a> {
+    pub input: &'a Path,
+    pub output: &'a Path,
+    pub result: Result<&'a Page, &'a Failure>,
+    /// Time spent on this file.
+    pub elapsed: Duration,
+    /// Number of finished files, including this one.
+    pub done: usize,
+    pub total: usize,
+}
+
+/// The converted pages and the failed files, in the input order.
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    pub pages: Vec<Page>,
+    pub failures: Vec<Failure>,
+}
+
+type ProgressCallback = Box<dyn Fn(&FileEvent) + Send + Sync>;
+type ErrorCallback = Box<dyn Fn(&Failure) + Send + Sync>;
+
+/// Converts HTML files into an output directory, the way `htmd --output <dir>` does.
+///
+/// ```no_run
+/// use htmd_cli::{BatchConverter, Config};
+///
+/// let files = vec!["pages/index.html".into(), "pages/guide/setup.html".into()];
+/// let result = BatchConverter::new(Config::default())
+///     .jobs(4)
+///     .on_progress(|event| println!("{}/{} {:?}", event.done, event.total, event.input))
+///     .on_error(|failure| eprintln!("{:?}: {}", failure.file, failure.reason))
+///     .convert(&files, "converted".as_ref())
+///     .unwrap();
+/// println!("Converted {} file(s)", result.pages.len());
+/// ```
+pub struct BatchConverter {
+    converter: Arc<PageConverter>,
+    flatten_output: bool,
+    layout: Option<Layout>,
+    titles: bool,
+    jobs: usize,
+    input_limit: InputLimit,
+    convert_limits: ConvertLimits,
+    on_progress: Option<ProgressCallback>,
+    on_error: Option<ErrorCallback>,
+}
+
+impl BatchConverter {
+    pub fn new(config: Config) -> Self {
+        let flatten_output = config.flatten_output;
+        Self::with_converter(Arc::new(PageConverter::new(config, false)), flatten_output)
+    }
+
+    pub(crate) fn with_converter(converter: Arc<PageConverter>, flatten_output: bool) -> Self {
+        BatchConverter {
+            converter,
+            flatten_output,
+            layout: None,
+            titles: false,
+            jobs: thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            input_limit: InputLimit::default(),
+            convert_limits: ConvertLimits::default(),
+            on_progress: None,
+            on_error: None,
+        }
+    }
+
+    /// Number of files to convert in parallel, defaults to the number of CPUs.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Write file names, front matter and the navigation file for a static site generator.
+    pub fn layout(mut self, layout: Option<Layout>) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Read the title of every page into [Page::title], for indexes. Layouts always read them.
+    pub fn titles(mut self, titles: bool) -> Self {
+        self.titles = titles;
+        self
+    }
+
+    pub fn input_limit(mut self, input_limit: InputLimit) -> Self {
+        self.input_limit = input_limit;
+        self
+    }
+
+    pub fn convert_limits(mut self, convert_limits: ConvertLimits) -> Self {
+        self.convert_limits = convert_limits;
+        self
+    }
+
+    /// Called after every file, from the worker threads.
+    pub fn on_progress(mut self, callback: impl Fn(&FileEvent) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Called for every file that failed to convert, from the worker threads.
+    pub fn on_error(mut self, callback: impl Fn(&Failure) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Box::new(callback));
+        self
+    }
+
+    /// Map input files to their output files, without converting anything.
+    ///
+    /// Files keep their paths relative to the common ancestor of all inputs, unless
+    /// `flatten_output` is set.
+    pub fn plan(&self, files: &[PathBuf], output: &Path) -> Vec<PlanEntry> {
+        let Some(base_dir) = common_ancestor(files) else {
+            return vec![];
+        };
+        files
+            .iter()
+            .map(|file| PlanEntry {
+                input: file.clone(),
+                output: output_file_path(file, self.flatten_output, &base_dir, output, self.layout),
+            })
+            .collect()
+    }
+
+    /// Convert files into the output directory.
+    ///
+    /// Files that cannot be read, converted or written are returned as failures, the
+    /// error is only returned if the output directory cannot be created.
+    pub fn convert(&self, files: &[PathBuf], output: &Path) -> io::Result<BatchResult> {
+        if output.is_file() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("Output is not a directory: {:?}", output),
+            ));
+        }
+        fs::create_dir_all(output)?;
+
+        let plan = self.plan(files, output);
+        let output_files = plan
+            .iter()
+            .map(|entry| entry.output.clone())
+            .collect::<Vec<PathBuf>>();
+        let layout_entries = self
+            .layout
+            .map(|layout| plan_layout(layout, output, &output_files))
+            .unwrap_or_default();
+        // Layouts rename files, links between the pages follow them
+        let page_links = self.layout.map(|_| {
+            plan.iter()
+                .map(|entry| (normalize_path(&entry.input), entry.output.clone()))
+                .collect::<HashMap<PathBuf, PathBuf>>()
+        });
+
+        let total = plan.len();
+        let done = AtomicUsize::new(0);
+        let tasks = plan.iter().enumerate().collect::<Vec<_>>();
+        let results = run_parallel(&tasks, self.jobs, |(index, entry)| {
+            let start = Instant::now();
+            let result = self.convert_entry(entry, layout_entries.get(*index), page_links.as_ref());
+            if let (Err(failure), Some(on_error)) = (&result, &self.on_error) {
+                on_error(failure);
+            }
+            if let Some(on_progress) = &self.on_progress {
+                on_progress(&FileEvent {
+                    input: &entry.input,
+                    output: &entry.output,
+                    result: result.as_ref(),
+                    elapsed: start.elapsed(),
+                    done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                    total,
+                });
+            }
+            result
+        });
+
+        let mut batch_result = BatchResult::default();
+        for result in results {
+            match result {
+                Ok(page) => batch_result.pages.push(page),
+                Err(failure) => batch_result.failures.push(failure),
+            }
+        }
+        Ok(batch_result)
+    }
+
+    /// Convert a single file to `output_file`.
+    pub fn convert_file(&self, file: &Path, output_file: &Path) -> Result<Page, Failure> {
+        let entry = PlanEntry {
+            input: file.to_path_buf(),
+            output: output_file.to_path_buf(),
+        };
+        let result = self.convert_entry(&entry, None, None);
+        if let (Err(failure), Some(on_error)) = (&result, &self.on_error) {
+            on_error(failure);
+        }
+        result
+    }
+
+    fn convert_entry(
+        &self,
+        entry: &PlanEntry,
+        layout_entry: Option<&LayoutEntry>,
+        page_links: Option<&HashMap<PathBuf, PathBuf>>,
+    ) -> Result<Page, Failure> {
+        let PlanEntry {
+            input: file,
+            output: output_file,
+        } = entry;
+        let fail = |reason: String| Failure {
+            file: file.clone(),
+            reason,
+        };
+
+        let html = read_html_file(file, self.input_limit).map_err(|e| fail(e.to_string()))?;
+
+        // The `<title>` is read before the html is moved into the converter
+        let reads_titles = self.titles || self.layout.is_some();
+        let html_title = reads_titles.then(|| html_title(&html));
+
+        let md = self
+            .converter
+            .convert(html, self.convert_limits)
+            .map_err(fail)?;
+
+        let title = html_title.map(|html_title| page_title(html_title, &md, file));
+        let md = match page_links {
+            Some(page_links) => rewrite_page_links(&md, file, output_file, page_links),
+            None => md,
+        };
+
+        if let Some(output_dir) = output_file.parent() {
+            if !output_dir.as_os_str().is_empty() && !output_dir.exists() {
+                fs::create_dir_all(output_dir).map_err(|e| {
+                    fail(format!("Cannot create output dir {:?}: {}", output_dir, e))
+                })?;
+            }
+        }
+
+        let front_matter = layout_entry
+            .zip(title.as_deref())
+            .and_then(|(entry, title)| entry.front_matter(title));
+        write_markdown(output_file, front_matter.as_deref(), &md)
+            .map_err(|e| fail(format!("Cannot write file {:?}: {}", output_file, e)))?;
+
+        Ok(Page {
+            title,
+            output: output_file.clone(),
+        })
+    }
+}
+
+/// Map an input file to its Markdown file in the output directory.
+fn output_file_path(
+    file: &Path,
+    flatten_output: bool,
+    base_dir: &Path,
+    output: &Path,
+    layout: Option<Layout>,
+) -> PathBuf {
+    let filename_with_ext = file.file_stem().unwrap().to_str().unwrap();
+    let output_filename = match layout {
+        Some(layout) => layout.output_filename(filename_with_ext),
+        None => format!("{}.md", filename_with_ext),
+    };
+    if !flatten_output {
+        let parent = file.parent().unwrap();
+        if parent != base_dir {
+            let relative = parent.strip_prefix(base_dir).unwrap();
+            output.join(relative).join(output_filename)
+        } else {
+            output.join(output_filename)
+        }
+    } else {
+        output.join(output_filename)
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use htmd::HtmlToMarkdown;
+use serde_json::{Map, Value};
+
+use crate::config_util::read_cli_options_from_json;
+
+/// Distinct option sets to keep converters for, the cache is cleared when it is full.
+const MAX_CACHED_CONVERTERS: usize = 64;
+
+/// Converters keyed by their options, building a converter takes longer than converting
+/// a small page.
+#[derive(Default)]
+pub(crate) struct ConverterCache {
+    converters: Mutex<HashMap<String, Arc<HtmlToMarkdown>>>,
+}
+
+impl ConverterCache {
+    /// The converter of `options`, which have the same names as the cli options.
+    pub(crate) fn get(&self, options: Map<String, Value>) -> Result<Arc<HtmlToMarkdown>, String> {
+        let options = Value::Object(options);
+        // Object keys are sorted, so the same options always have the same key
+        let key = options.to_string();
+        let mut converters = self.converters.lock().unwrap();
+        if let Some(converter) = converters.get(&key) {
+            return Ok(Arc::clone(converter));
+        }
+
+        let config = read_cli_options_from_json(&options).map_err(|e| e.to_string())?;
+        let converter = Arc::new(config.into_converter());
+        if converters.len() >= MAX_CACHED_CONVERTERS {
+            converters.clear();
+        }
+        converters.insert(key, Arc::clone(&converter));
+        Ok(converter)
+    }
+}
@@ -0,0 +1,79 @@
+use std::{process::exit, time::Instant};
+
+use clap::{ArgMatches, Command};
+
+use crate::{
+    cli_options::{check_conflicts, cli_args, parse_cli_options},
+    io_util::{read_html_file, resolve_input, resolve_run_options, Input},
+    limit_util::{exit_on_failures, Failure},
+    meta_util::PageConverter,
+    pool_util::run_parallel,
+    progress_util::Verbosity,
+};
+
+/// Args of `htmd convert` that decide where the output goes, or that skip converting.
+const OUTPUT_ARGS: [&str; 10] = [
+    "output",
+    "index",
+    "layout",
+    "merge",
+    "merge-order",
+    "merge-toc",
+    "stdout-mode",
+    "stdout-delimiter",
+    "stdio-worker",
+    "print-config",
+];
+
+pub(crate) fn check_command() -> Command {
+    Command::new("check")
+        .about("Convert inputs without writing any output, exits with 1 if a file fails")
+        .args(
+            cli_args()
+                .into_iter()
+                .filter(|arg| !OUTPUT_ARGS.contains(&arg.get_id().as_str())),
+        )
+}
+
+pub(crate) fn run_check(matches: &ArgMatches) {
+    let now = Instant::now();
+    check_conflicts(matches);
+    let run_options = resolve_run_options(matches);
+    let converter = PageConverter::new(parse_cli_options(matches), run_options.honor_meta_options);
+
+    let files = match resolve_input(matches, run_options.input_limit) {
+        Input::Stdin(text) => {
+            if let Err(e) = converter.convert(text, run_options.convert_limits) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+            return;
+        }
+        Input::Fs(files) => files,
+    };
+
+    let results = run_parallel(&files, run_options.jobs, |file| {
+        let fail = |reason: String| Failure {
+            file: file.clone(),
+            reason,
+        };
+        let html =
+            read_html_file(file, run_options.input_limit).map_err(|e| fail(e.to_string()))?;
+        converter
+            .convert(html, run_options.convert_limits)
+            .map_err(fail)
+    });
+    let failures = results
+        .into_iter()
+        .filter_map(|result| result.err())
+        .collect::<Vec<Failure>>();
+
+    if run_options.verbosity != Verbosity::Quiet {
+        println!(
+            "Checked {} file(s) in {:?}.",
+            files.len() - failures.len(),
+            now.elapsed()
+        );
+    }
+    exit_on_failures(&failures);
+}
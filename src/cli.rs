@@ -0,0 +1,395 @@
+//! The `htmd` command line, the binary only calls [run].
+
+use std::{
+    env::current_dir,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::Arc,
+    time::Instant,
+    vec,
+};
+
+use clap::{ArgMatches, Command};
+
+use crate::{
+    batch::{BatchConverter, BatchResult},
+    check_util::{check_command, run_check},
+    cli_options::{check_conflicts, cli_args, parse_cli_options, print_config},
+    completion_util::{completions_command, man_command, print_completions, print_man_page},
+    config_util::{config_command, run_config_command},
+    index_util::write_index,
+    io_util::{
+        read_html_file, resolve_input, resolve_output, resolve_output_options, resolve_run_options,
+        write_markdown, Input, InputLimit, Output, OutputOptions, RunOptions, StdoutMode,
+    },
+    limit_util::{exit_on_failures, ConvertLimits, Failure},
+    mcp_util::{mcp_command, run_mcp_server},
+    merge_util::{merge_documents, order_files, MergeOrder},
+    meta_util::PageConverter,
+    path_util::common_ancestor,
+    pool_util::run_parallel,
+    progress_util::{Progress, Verbosity},
+    server_util::{serve, serve_command},
+    worker_util::run_stdio_worker,
+};
+
+const SUMMARY: &str = "The command line tool for htmd, an HTML to Markdown converter";
+
+const ABOUT: &str = r#"
+The command line tool for htmd, an HTML to Markdown converter
+
+Examples: 
+  htmd # Read input from stdin
+  htmd index.html
+  htmd --input ./pages --output ./pages/md
+  htmd -i *.html -o ./md"#;
+
+/// The whole command line, also used to generate the completions and the man page.
+///
+/// `htmd <input>` without a subcommand is the same as `htmd convert <input>`.
+fn cli_command() -> Command {
+    Command::new("htmd")
+        .about(ABOUT)
+        .version(env!("CARGO_PKG_VERSION"))
+        .args(cli_args())
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("convert")
+                .about("Convert HTML to Markdown, the same as without a subcommand")
+                .args(cli_args()),
+        )
+        .subcommand(config_command())
+        .subcommand(check_command())
+        .subcommand(serve_command())
+        .subcommand(mcp_command())
+        .subcommand(completions_command())
+        .subcommand(man_command())
+}
+
+/// Parse the process arguments and run the command.
+pub fn run() {
+    let matches = cli_command().get_matches();
+
+    match matches.subcommand() {
+        Some(("convert", convert_matches)) => run_convert(convert_matches),
+        Some(("config", config_matches)) => run_config_command(config_matches),
+        Some(("check", check_matches)) => run_check(check_matches),
+        Some(("serve", serve_matches)) => serve(serve_matches),
+        Some(("mcp", mcp_matches)) => run_mcp_server(mcp_matches),
+        Some(("completions", completions_matches)) => {
+            print_completions(completions_matches, cli_command())
+        }
+        Some(("man", _)) => {
+            // The man page takes its NAME line from `about`, the examples go into the description
+            print_man_page(cli_command().about(SUMMARY).long_about(ABOUT))
+        }
+        _ => run_convert(&matches),
+    }
+}
+
+fn run_convert(matches: &ArgMatches) {
+    let now = Instant::now();
+    check_conflicts(matches);
+
+    if matches.get_flag("stdio-worker") {
+        let run_options = resolve_run_options(matches);
+        let converter =
+            PageConverter::new(parse_cli_options(matches), run_options.honor_meta_options);
+        run_stdio_worker(&converter, run_options.convert_limits);
+        return;
+    }
+
+    if matches.get_flag("print-config") {
+        print_config(matches);
+        return;
+    }
+
+    let config = parse_cli_options(matches);
+    let flatten_output = config.flatten_output;
+
+    let run_options = resolve_run_options(matches);
+    let input = resolve_input(matches, run_options.input_limit);
+    let output = resolve_output(matches);
+    let output_options = resolve_output_options(matches);
+
+    let converter = Arc::new(PageConverter::new(config, run_options.honor_meta_options));
+
+    match input {
+        Input::Stdin(text) => convert_text(&converter, text, &output, run_options.convert_limits),
+        Input::Fs(files) => {
+            let failures = convert_files(
+                converter,
+                &files,
+                &output,
+                flatten_output,
+                output_options,
+                &run_options,
+            );
+            let converted = files.len() - failures.len();
+            if run_options.verbosity != Verbosity::Quiet {
+                if let Output::Merge(merge) = &output {
+                    println!(
+                        "Merged {} file(s) into {:?} in {:?}.",
+                        converted,
+                        merge,
+                        now.elapsed()
+                    );
+                } else if output != Output::Stdout {
+                    println!("Converted {} file(s) in {:?}.", converted, now.elapsed());
+                }
+            }
+            exit_on_failures(&failures);
+        }
+    }
+}
+
+/// Convert files to the output, returns the files that failed to convert.
+fn convert_files(
+    converter: Arc<PageConverter>,
+    files: &[PathBuf],
+    output: &Output,
+    flatten_output: bool,
+    output_options: OutputOptions,
+    run_options: &RunOptions,
+) -> Vec<Failure> {
+    let OutputOptions {
+        stdout_mode,
+        merge_order,
+        index_mode,
+        layout,
+    } = output_options;
+    let input_limit = run_options.input_limit;
+
+    match output {
+        Output::Stdout => {
+            if let Some(stdout_mode) = stdout_mode {
+                return write_files_to_stdout(&converter, files, &stdout_mode, run_options);
+            } else if files.len() > 1 {
+                let cwd = current_dir().expect("Cannot get current dir.");
+                let paths = files
+                    .iter()
+                    .map(|file| format!("  {:?}", file.strip_prefix(&cwd).unwrap_or(file)))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                eprintln!(
+                    "Output to stdout doesn't support multiple files as the input.\n\n\
+                    Input files:\n{}\n\n\
+                    Try to use a folder as the output:\n  --output converted\n\n\
+                    Or choose how to write them to stdout:\n  --stdout-mode concat",
+                    paths
+                );
+                exit(1);
+            } else {
+                let file = &files[0];
+                let text = read_html_or_exit(file, input_limit);
+                convert_text(
+                    &converter,
+                    text,
+                    &Output::Stdout,
+                    run_options.convert_limits,
+                );
+            }
+            vec![]
+        }
+        Output::Fs(output) => {
+            if files.is_empty() {
+                println!("Nothing to convert.");
+                exit(0);
+            }
+
+            let progress = Arc::new(Progress::new(run_options.verbosity));
+            let batch = BatchConverter::with_converter(converter, flatten_output)
+                .jobs(run_options.jobs)
+                .layout(layout)
+                .titles(index_mode.is_some())
+                .input_limit(input_limit)
+                .convert_limits(run_options.convert_limits)
+                .on_progress({
+                    let progress = Arc::clone(&progress);
+                    move |event| progress.file_done(event)
+                });
+
+            let output_as_dir = output.extension().is_none();
+            if files.len() == 1 && !output_as_dir {
+                return batch
+                    .convert_file(&files[0], output)
+                    .err()
+                    .into_iter()
+                    .collect();
+            }
+
+            let result = batch.convert(files, output).unwrap_or_else(|e| {
+                eprintln!("Cannot write to the output directory: {}", e);
+                exit(1);
+            });
+            progress.finish();
+            let BatchResult { pages, failures } = result;
+
+            if let Some(index_mode) = index_mode {
+                write_index(output, &pages, index_mode);
+            }
+            if let Some(layout) = layout {
+                layout.write_nav(output, &pages);
+            }
+            failures
+        }
+        Output::Merge(merge) => {
+            if files.is_empty() {
+                println!("Nothing to convert.");
+                exit(0);
+            }
+            convert_multiple_and_merge(converter, files, merge, &merge_order, run_options)
+        }
+    }
+}
+
+fn convert_text(
+    converter: &Arc<PageConverter>,
+    text: String,
+    output: &Output,
+    convert_limits: ConvertLimits,
+) {
+    let md = converter.convert(text, convert_limits).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    match output {
+        Output::Stdout => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            out.write_all(md.as_bytes())
+                .and_then(|_| out.flush())
+                .expect("Failed to write to stdout");
+        }
+        Output::Fs(file) | Output::Merge(file) => {
+            if file.exists() && file.is_dir() {
+                eprintln!("Output cannot be a directory.");
+                exit(1);
+            }
+            write_markdown(file, None, &md)
+                .unwrap_or_else(|_| panic!("Failed to write to file: {:?}", file))
+        }
+    }
+}
+
+/// Write the files to stdout one after another, returns the files that failed to convert.
+fn write_files_to_stdout(
+    converter: &Arc<PageConverter>,
+    files: &[PathBuf],
+    mode: &StdoutMode,
+    run_options: &RunOptions,
+) -> Vec<Failure> {
+    let cwd = current_dir().expect("Cannot get current dir.");
+
+    // Keep the output stable regardless of how the input files were collected
+    let mut files = files.to_vec();
+    files.sort();
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut failures = vec![];
+    let mut written = 0;
+    for file in files.iter() {
+        let md = read_html_file(file, run_options.input_limit)
+            .map_err(|e| e.to_string())
+            .and_then(|html| converter.convert(html, run_options.convert_limits));
+        let md = match md {
+            Ok(md) => md,
+            Err(reason) => {
+                failures.push(Failure {
+                    file: file.clone(),
+                    reason,
+                });
+                continue;
+            }
+        };
+        let path = display_path(file, &cwd);
+
+        let separator = if written > 0 { "\n\n" } else { "" };
+
+        let result = match mode {
+            StdoutMode::Concat => write!(out, "{}{}", separator, md),
+            StdoutMode::Delimited(delimiter) => write!(
+                out,
+                "{}{}\n\n{}",
+                separator,
+                delimiter.replace("{path}", &path),
+                md
+            ),
+            StdoutMode::Ndjson => writeln!(
+                out,
+                "{{\"path\":{},\"markdown\":{}}}",
+                serde_json::Value::from(path),
+                serde_json::Value::from(md)
+            ),
+        };
+        result.expect("Failed to write to stdout");
+        written += 1;
+    }
+    out.flush().expect("Failed to write to stdout");
+    failures
+}
+
+fn display_path(file: &Path, cwd: &Path) -> String {
+    file.strip_prefix(cwd)
+        .unwrap_or(file)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn convert_multiple_and_merge(
+    converter: Arc<PageConverter>,
+    files: &[PathBuf],
+    output: &PathBuf,
+    merge_order: &MergeOrder,
+    run_options: &RunOptions,
+) -> Vec<Failure> {
+    if output.exists() && output.is_dir() {
+        eprintln!("Merge output cannot be a directory.");
+        exit(1);
+    }
+
+    let files = order_files(files, merge_order);
+
+    let base_dir = &common_ancestor(&files).unwrap();
+
+    let docs = run_parallel(&files, run_options.jobs, |file| {
+        let fail = |reason: String| Failure {
+            file: file.clone(),
+            reason,
+        };
+        let html =
+            read_html_file(file, run_options.input_limit).map_err(|e| fail(e.to_string()))?;
+        converter
+            .convert(html, run_options.convert_limits)
+            .map_err(fail)
+    });
+    let (converted, failures): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .zip(docs)
+        .map(|(file, doc)| doc.map(|doc| (file, doc)))
+        .partition(Result::is_ok);
+    let (files, docs): (Vec<PathBuf>, Vec<String>) = converted.into_iter().flatten().unzip();
+    let failures = failures.into_iter().filter_map(Result::err).collect();
+
+    let merged = merge_documents(&files, &docs, base_dir);
+
+    if let Some(output_dir) = output.parent() {
+        if !output_dir.as_os_str().is_empty() && !output_dir.exists() {
+            fs::create_dir_all(output_dir)
+                .unwrap_or_else(|_| panic!("Cannot create output dir: {:?}", output_dir));
+        }
+    }
+
+    write_markdown(output, None, &merged)
+        .unwrap_or_else(|_| panic!("Cannot write file: {:?}", output));
+
+    failures
+}
+
+fn read_html_or_exit(file: &Path, input_limit: InputLimit) -> String {
+    read_html_file(file, input_limit).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    })
+}
@@ -1,126 +1,237 @@
-use crate::config_util::read_cli_options_from_toml_file;
-use clap::{value_parser, Arg, ArgAction, ArgMatches};
-use htmd::options::{
-    BrStyle, BulletListMarker, CodeBlockFence, CodeBlockStyle, HeadingStyle, HrStyle,
-    LinkReferenceStyle, LinkStyle, Options,
+use std::{collections::HashMap, fmt, path::Path, process::exit};
+
+use crate::{
+    config::Config,
+    config_util::{
+        options_format_arg, read_cli_options_from_json, read_options_value_from_file,
+        resolve_options_format, write_cli_options,
+    },
+    io_util::parse_size,
+    limit_util::parse_duration,
+    option_util::{parse_bool, OptionKind, OPTIONS},
+};
+use clap::{
+    error::ErrorKind, parser::ValueSource, value_parser, Arg, ArgAction, ArgMatches, ValueHint,
 };
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use toml::Value;
+
+/// Args that cannot be used together. They are not clap conflicts, because a flag takes
+/// precedence over the `HTMD_*` variable of the other arg.
+const CONFLICTING_ARGS: [(&str, &str); 2] = [("quiet", "verbose"), ("merge", "output")];
 
-pub(crate) struct CliOptions {
-    pub converter_options: Options,
-    pub ignored_tags: Option<Vec<String>>,
-    pub flatten_output: bool,
-    pub scripting_enabled: bool,
+/// Where the value of an option comes from, later sources take precedence.
+enum OptionSource {
+    Default,
+    OptionsFile(String),
+    Env(String),
+    CommandLine(&'static str),
 }
 
-pub(crate) fn parse_cli_options(matches: &ArgMatches) -> CliOptions {
-    if let Some(config) = matches.get_one::<String>("options-file") {
-        read_cli_options_from_toml_file(config).expect("Failed to parse options from config file")
-    } else {
-        CliOptions {
-            converter_options: parse_converter_options_from_cli_args(matches),
-            ignored_tags: parse_ignored_tags(matches),
-            flatten_output: *matches.get_one::<bool>("flatten-output").unwrap(),
-            scripting_enabled: *matches.get_one::<bool>("scripting-enabled").unwrap(),
+impl fmt::Display for OptionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionSource::Default => write!(f, "default"),
+            OptionSource::OptionsFile(file) => write!(f, "options file {:?}", file),
+            OptionSource::Env(name) => write!(f, "{}", name),
+            OptionSource::CommandLine(name) => write!(f, "--{}", name),
         }
     }
 }
 
-fn parse_ignored_tags(matches: &ArgMatches) -> Option<Vec<String>> {
-    let Some(tags_str) = matches.get_one::<String>("ignored-tags") else {
-        return None;
-    };
-    Some(tags_str.split(",").map(|tag| tag.to_string()).collect())
+pub(crate) fn parse_cli_options(matches: &ArgMatches) -> Config {
+    let (options, _) = resolve_options(matches);
+    read_cli_options_from_json(&JsonValue::Object(options))
+        .expect("Cli options are validated by clap")
 }
 
-fn parse_converter_options_from_cli_args(matches: &ArgMatches) -> Options {
-    let heading_style = match matches.get_one::<String>("heading-style").unwrap().as_str() {
-        "setex" => HeadingStyle::Setex,
-        "atx" | _ => HeadingStyle::Atx,
+/// Print the options of a run as an options file, with the source of every option.
+pub(crate) fn print_config(matches: &ArgMatches) {
+    let (options, sources) = resolve_options(matches);
+    let config = read_cli_options_from_json(&JsonValue::Object(options))
+        .expect("Cli options are validated by clap");
+    let Value::Table(values) = write_cli_options(&config) else {
+        unreachable!("Options are written to a table");
     };
+    println!("[options]");
+    for option in &OPTIONS {
+        // Lists have no default in the cli, so they have no source unless they are set
+        let source = sources.get(option.name).unwrap_or(&OptionSource::Default);
+        if let Some(value) = values.get(option.name) {
+            println!("{} = {} # {}", option.name, value, source);
+        }
+    }
+}
 
-    let hr_style = match matches.get_one::<String>("hr-style").unwrap().as_str() {
-        "dashes" => HrStyle::Dashes,
-        "underscores" => HrStyle::Underscores,
-        "asterisks" | _ => HrStyle::Asterisks,
-    };
+/// Layer the options: defaults of the flavor, the options file, `HTMD_*` variables, then cli flags.
+fn resolve_options(
+    matches: &ArgMatches,
+) -> (
+    JsonMap<String, JsonValue>,
+    HashMap<&'static str, OptionSource>,
+) {
+    let mut options = JsonMap::new();
+    let mut sources = HashMap::new();
 
-    let br_style = match matches.get_one::<String>("br-style").unwrap().as_str() {
-        "backslash" => BrStyle::Backslash,
-        "two-spaces" | _ => BrStyle::TwoSpaces,
-    };
+    if let Some(file) = matches.get_one::<String>("options-file") {
+        let format = resolve_options_format(matches);
+        let file_options =
+            read_options_value_from_file(Path::new(file), format).unwrap_or_else(|e| {
+                eprintln!("Invalid options file {:?}: {}", file, e);
+                exit(1);
+            });
+        for option in &OPTIONS {
+            if let Some(value) = file_options.get(option.name) {
+                options.insert(option.name.to_string(), value.clone());
+                sources.insert(option.name, OptionSource::OptionsFile(file.clone()));
+            }
+        }
+    }
 
-    let link_style = match matches.get_one::<String>("link-style").unwrap().as_str() {
-        "referenced" => LinkStyle::Referenced,
-        "inlined-prefer-autolinks" => LinkStyle::InlinedPreferAutolinks,
-        "inlined" | _ => LinkStyle::Inlined,
-    };
+    for (name, value) in options_from_matches(matches) {
+        let source = match matches.value_source(name) {
+            Some(ValueSource::CommandLine) => OptionSource::CommandLine(name),
+            Some(ValueSource::EnvVariable) => OptionSource::Env(env_var_name(name)),
+            _ if options.contains_key(name) => continue,
+            // Defaults are left out, so they follow the flavor
+            _ => {
+                sources.insert(name, OptionSource::Default);
+                continue;
+            }
+        };
+        options.insert(name.to_string(), value);
+        sources.insert(name, source);
+    }
 
-    let link_reference_style = match matches
-        .get_one::<String>("link-reference-style")
-        .unwrap()
-        .as_str()
-    {
-        "collapsed" => LinkReferenceStyle::Collapsed,
-        "shortcut" => LinkReferenceStyle::Shortcut,
-        "full" | _ => LinkReferenceStyle::Full,
-    };
+    (options, sources)
+}
 
-    let code_block_style = match matches
-        .get_one::<String>("code-block-style")
-        .unwrap()
-        .as_str()
-    {
-        "indented" => CodeBlockStyle::Indented,
-        "fenced" | _ => CodeBlockStyle::Fenced,
-    };
+/// Map the cli options to option values, so they are read the same way as options files.
+fn options_from_matches(matches: &ArgMatches) -> Vec<(&'static str, JsonValue)> {
+    let mut options = vec![];
+    for option in &OPTIONS {
+        let value = match option.kind {
+            OptionKind::Value(_) => matches
+                .get_one::<String>(option.name)
+                .map(|value| JsonValue::from(value.as_str())),
+            OptionKind::Bool => matches
+                .get_one::<bool>(option.name)
+                .map(|value| JsonValue::from(*value)),
+            OptionKind::U8 => matches
+                .get_one::<u8>(option.name)
+                .map(|value| JsonValue::from(*value)),
+            OptionKind::List => matches
+                .get_one::<String>(option.name)
+                .map(|tags| JsonValue::from(tags.split(',').collect::<Vec<&str>>())),
+        };
+        if let Some(value) = value {
+            options.push((option.name, value));
+        }
+    }
+    options
+}
 
-    let code_block_fence = match matches
-        .get_one::<String>("code-block-fence")
-        .unwrap()
-        .as_str()
-    {
-        "tildes" => CodeBlockFence::Tildes,
-        "backticks" | _ => CodeBlockFence::Backticks,
-    };
+/// Exit like clap if conflicting args come from the same level, both flags or both variables.
+pub(crate) fn check_conflicts(matches: &ArgMatches) {
+    for (first, second) in CONFLICTING_ARGS {
+        let (Some(first_source), Some(second_source)) =
+            (arg_source(matches, first), arg_source(matches, second))
+        else {
+            continue;
+        };
+        if first_source == second_source {
+            clap::Error::raw(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "the argument '--{}' cannot be used with '--{}'\n",
+                    first, second
+                ),
+            )
+            .exit();
+        }
+    }
+}
 
-    let bullet_list_marker = match matches
-        .get_one::<String>("bullet-list-marker")
-        .unwrap()
-        .as_str()
-    {
-        "dash" => BulletListMarker::Dash,
-        "asterisk" | _ => BulletListMarker::Asterisk,
-    };
+/// Whether an arg comes from its `HTMD_*` variable and a conflicting arg is a flag, then the
+/// variable is ignored.
+pub(crate) fn is_overridden(matches: &ArgMatches, id: &str) -> bool {
+    is_from_env(matches, id)
+        && CONFLICTING_ARGS
+            .iter()
+            .filter_map(|&(first, second)| match id {
+                _ if id == first => Some(second),
+                _ if id == second => Some(first),
+                _ => None,
+            })
+            .any(|other| arg_source(matches, other) == Some(ValueSource::CommandLine))
+}
+
+/// Whether an arg is set by its `HTMD_*` variable.
+pub(crate) fn is_from_env(matches: &ArgMatches, id: &str) -> bool {
+    arg_source(matches, id) == Some(ValueSource::EnvVariable)
+}
 
-    let preformatted_code = *matches.get_one::<bool>("preformatted-code").unwrap();
-
-    let ul_bullet_spacing = *matches.get_one::<u8>("ul-bullet-spacing").unwrap();
-
-    let ol_number_spacing = *matches.get_one::<u8>("ol-number-spacing").unwrap();
-
-    Options {
-        heading_style,
-        hr_style,
-        br_style,
-        link_style,
-        link_reference_style,
-        code_block_style,
-        code_block_fence,
-        bullet_list_marker,
-        ul_bullet_spacing,
-        ol_number_spacing,
-        preformatted_code,
-        translation_mode: htmd::options::TranslationMode::Pure,
+/// The source of an arg that is set, defaults of flags are not a source.
+fn arg_source(matches: &ArgMatches, id: &str) -> Option<ValueSource> {
+    // `value_source` panics for args that a subcommand does not have
+    if !matches.ids().any(|arg| arg.as_str() == id) {
+        return None;
     }
+    matches
+        .value_source(id)
+        .filter(|source| *source != ValueSource::DefaultValue)
+}
+
+/// The environment variable of an arg, e.g. `HTMD_LINK_STYLE` for `--link-style`.
+fn env_var_name(id: &str) -> String {
+    format!("HTMD_{}", id.to_uppercase().replace('-', "_"))
+}
+
+/// The args of the [OPTIONS] table, defaults are the same as in options files.
+fn option_args() -> Vec<Arg> {
+    let Value::Table(defaults) = write_cli_options(&Config::default()) else {
+        unreachable!("Options are written to a table");
+    };
+    OPTIONS
+        .iter()
+        .map(|option| {
+            let arg = Arg::new(option.name).long(option.name).help(option.help);
+            let default = defaults.get(option.name);
+            match (&option.kind, default) {
+                (OptionKind::Value(names), Some(Value::String(default))) => arg
+                    .num_args(1)
+                    .default_value(default.clone())
+                    .default_missing_value(default.clone())
+                    .value_parser(names()),
+                // Options that are off by default are flags
+                (OptionKind::Bool, Some(Value::Boolean(false))) => arg.action(ArgAction::SetTrue),
+                // Also 1/0, yes/no, and on/off, like the flags
+                (OptionKind::Bool, _) => arg
+                    .value_parser(parse_bool)
+                    .default_value("true")
+                    .required(false),
+                (OptionKind::U8, Some(default)) => arg
+                    .value_parser(value_parser!(u8))
+                    .default_value(default.to_string())
+                    .required(false),
+                (OptionKind::List, _) => arg
+                    .help(format!("{}, separated by commas", option.help))
+                    .num_args(1),
+                _ => unreachable!("Every option has a default"),
+            }
+        })
+        .collect()
 }
 
+/// The args of `htmd convert`, every named arg can also be set by its `HTMD_*` variable.
 pub(crate) fn cli_args() -> Vec<Arg> {
     vec![
-        Arg::new("input-unnamed").index(1).num_args(1),
+        Arg::new("input-unnamed").index(1).num_args(1).value_hint(ValueHint::AnyPath),
         Arg::new("input")
             .short('i')
             .long("input")
             .help("Specify input. Can be stdin ('-'), file, directory, or glob pattern; defaults to stdin")
+            .value_hint(ValueHint::AnyPath)
             .num_args(1),
         Arg::new("output")
             .short('o')
@@ -128,96 +239,150 @@ pub(crate) fn cli_args() -> Vec<Arg> {
             .help(
                 "Specify output. Can be stdout ('-'), file, or directory; defaults to stdout",
             )
+            .value_hint(ValueHint::AnyPath)
             .num_args(1),
-        Arg::new("options-file")
-            .long("options-file")
+        Arg::new("index")
+            .long("index")
             .help(
-                "Read cli options from a toml file. Options are within [options] section;\n\
-                if specified, other options will be ignored except for input and output",
+                "Write an index of the converted pages: 'dir' writes a README.md into\n\
+                every output folder, '--index=summary' writes one mdBook SUMMARY.md",
             )
+            .num_args(0..=1)
+            // So `--index <input>` does not take the input as its value
+            .require_equals(true)
+            .default_missing_value("dir")
+            .value_parser(["dir", "summary"]),
+        Arg::new("layout")
+            .long("layout")
+            .help(
+                "Write files for a static site generator: file names, front matter, and\n\
+                the navigation file (SUMMARY.md for mdbook, mkdocs-nav.yml for mkdocs)",
+            )
+            .num_args(1)
+            .value_parser(["hugo", "docusaurus", "mdbook", "mkdocs"]),
+        Arg::new("merge")
+            .long("merge")
+            .help("Merge all converted files into a single Markdown file")
+            .value_hint(ValueHint::FilePath)
             .num_args(1),
-        Arg::new("flatten-output")
-            .long("flatten-output")
-            .help("Flat the output files in the output folder")
-            .action(ArgAction::SetTrue),
-        Arg::new("ignored-tags")
-            .long("ignored-tags")
-            .help("Set an HTML tag list to be ignored, separated by commas")
+        Arg::new("merge-order")
+            .long("merge-order")
+            .help("Order of the merged files")
+            .num_args(1)
+            .default_value("sorted")
+            .value_parser(["sorted", "natural"]),
+        Arg::new("merge-toc")
+            .long("merge-toc")
+            .help(
+                "Order the merged files by a list file, one path or Markdown link per line;\n\
+                paths are relative to the list file, unlisted files are appended",
+            )
+            .value_hint(ValueHint::FilePath)
             .num_args(1),
-        Arg::new("scripting-enabled")
-            .long("scripting-enabled")
-            .help("Option for HTML parsing. Set to false to parse contents in <noscript>")
-            .value_parser(value_parser!(bool))
-            .default_value("true")
-            .required(false),
-        Arg::new("heading-style")
-            .long("heading-style")
+        Arg::new("stdout-mode")
+            .long("stdout-mode")
+            .help(
+                "How to write multiple input files to stdout: concat, delimited, or ndjson;\n\
+                input files are written in sorted order",
+            )
             .num_args(1)
-            .default_value("atx")
-            .default_missing_value("atx")
-            .value_parser(["atx", "setex"]),
-        Arg::new("hr-style")
-            .long("hr-style")
+            .value_parser(["concat", "delimited", "ndjson"]),
+        Arg::new("stdout-delimiter")
+            .long("stdout-delimiter")
+            .help(
+                "Separator line written before each document in delimited stdout mode;\n\
+                '{path}' is replaced with the source file path",
+            )
             .num_args(1)
-            .default_value("asterisks")
-            .default_missing_value("asterisks")
-            .value_parser(["dashes", "asterisks", "underscores"]),
-        Arg::new("br-style")
-            .long("br-style")
+            .default_value("<!-- {path} -->"),
+        Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .help("Number of files to convert in parallel; defaults to the number of CPUs")
             .num_args(1)
-            .default_value("two-spaces")
-            .default_missing_value("two-spaces")
-            .value_parser(["two-spaces", "backslash"]),
-        Arg::new("link-style")
-            .long("link-style")
+            .value_parser(value_parser!(u32).range(1..)),
+        Arg::new("max-input-size")
+            .long("max-input-size")
+            .help("Maximum size of an HTML input, e.g. 1048576, 512K, 100M or 1G")
             .num_args(1)
-            .default_value("inlined")
-            .default_missing_value("inlined")
-            .value_parser(["inlined", "inlined-prefer-autolinks", "referenced"]),
-        Arg::new("link-reference-style")
-            .long("link-reference-style")
+            .value_parser(parse_size),
+        Arg::new("oversize")
+            .long("oversize")
+            .help("What to do with inputs larger than --max-input-size")
             .num_args(1)
-            .default_value("full")
-            .default_missing_value("full")
-            .value_parser(["full", "collapsed", "shortcut"]),
-        Arg::new("code-block-style")
-            .long("code-block-style")
+            .default_value("reject")
+            .value_parser(["reject", "truncate"]),
+        Arg::new("timeout-per-file")
+            .long("timeout-per-file")
+            .help(
+                "Give up converting a file after this time, e.g. 10s or 500ms. Files are\n\
+                converted on threads of their own, timed out threads keep running until\n\
+                they finish or htmd exits",
+            )
             .num_args(1)
-            .default_value("fenced")
-            .default_missing_value("fenced")
-            .value_parser(["fenced", "intended"]),
-        Arg::new("code-block-fence")
-            .long("code-block-fence")
+            .value_parser(parse_duration),
+        Arg::new("max-depth")
+            .long("max-depth")
+            .help("Skip files with elements nested deeper than this")
             .num_args(1)
-            .default_value("backticks")
-            .default_missing_value("backticks")
-            .value_parser(["tildes", "backticks"]),
-        Arg::new("bullet-list-marker")
-            .long("bullet-list-marker")
+            .value_parser(value_parser!(usize)),
+        Arg::new("max-nodes")
+            .long("max-nodes")
+            .help("Skip files with more elements than this")
             .num_args(1)
-            .default_value("asterisk")
-            .default_missing_value("asterisk")
-            .value_parser(["dash", "asterisk"]),
-        Arg::new("preformatted-code")
-            .long("preformatted-code")
-            .help("Preserve whitespace in inline code tags")
+            .value_parser(value_parser!(usize)),
+        Arg::new("options-file")
+            .long("options-file")
+            .help(
+                "Read options from a toml, yaml, or json file. Options are within the options\n\
+                table; HTMD_* variables and cli flags take precedence over them",
+            )
+            .value_hint(ValueHint::FilePath)
+            .num_args(1),
+        options_format_arg(),
+        Arg::new("honor-meta-options")
+            .long("honor-meta-options")
+            .help(
+                "Let pages override options for themselves with meta tags, like\n\
+                <meta name=\"htmd:heading-style\" content=\"setex\">",
+            )
             .action(ArgAction::SetTrue),
-        Arg::new("ul-bullet-spacing")
-            .long("ul-bullet-spacing")
-            .help("Spaces between ul bullet chars and contents")
-            .value_parser(value_parser!(u8))
-            .default_value("3")
-            .required(false),
-        Arg::new("ol-number-spacing")
-            .long("ol-number-spacing")
-            .help("Spaces between ol period chars and contents")
-            .value_parser(value_parser!(u8))
-            .default_value("2")
-            .required(false),
-        Arg::new("version")
-            .short('v')
-            .long("version")
-            .help("Print version")
-            .num_args(0),
     ]
+    .into_iter()
+    .chain(option_args())
+    .chain([
+        Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .help("Do not print progress and the summary")
+            .action(ArgAction::SetTrue),
+        Arg::new("verbose")
+            .short('v')
+            .long("verbose")
+            .help("Log every converted file, use -vv to include timings")
+            .action(ArgAction::Count),
+        Arg::new("stdio-worker")
+            .long("stdio-worker")
+            .help(
+                "Read {\"id\", \"html\", \"options\"} JSON lines from stdin and write\n\
+                {\"id\", \"markdown\"} or {\"id\", \"error\"} JSON lines to stdout",
+            )
+            .action(ArgAction::SetTrue),
+        Arg::new("print-config")
+            .long("print-config")
+            .help("Print the options with their source (default, options file, HTMD_* variable,\nor flag) and exit")
+            .action(ArgAction::SetTrue),
+    ])
+    .map(|arg| {
+        if arg.get_long().is_none() {
+            return arg;
+        }
+        let name = env_var_name(arg.get_id().as_str());
+        // Flags from variables can also be 1/0, yes/no, or on/off
+        match arg.get_action() {
+            ArgAction::SetTrue => arg.env(name).value_parser(parse_bool),
+            _ => arg.env(name),
+        }
+    })
+    .collect()
 }
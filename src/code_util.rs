@@ -0,0 +1,343 @@
+use std::rc::Rc;
+
+use htmd::{
+    element_handler::{HandlerResult, Handlers},
+    options::{CodeBlockFence, CodeBlockStyle},
+    Element, HtmlToMarkdownBuilder,
+};
+use markup5ever_rcdom::{Node, NodeData};
+
+use crate::flavor_util::{node_attr, parent_node, tag_name};
+
+/// Conversion options of code blocks.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CodeOptions {
+    /// Class patterns of the language of a code block, `*` is the language,
+    /// e.g. `language-*` finds `rust` in `class="language-rust"`.
+    pub language_classes: Vec<String>,
+    /// Keep the line numbers of highlighted code blocks.
+    pub line_numbers: bool,
+}
+
+impl Default for CodeOptions {
+    fn default() -> Self {
+        CodeOptions {
+            language_classes: DEFAULT_LANGUAGE_CLASSES
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+            line_numbers: false,
+        }
+    }
+}
+
+/// `highlight-source-*` (GitHub) comes before `highlight-*` (Sphinx), so it finds `rust`
+/// instead of `source-rust`.
+const DEFAULT_LANGUAGE_CLASSES: [&str; 4] =
+    ["language-*", "lang-*", "highlight-source-*", "highlight-*"];
+
+/// Attributes with the language of a code block, before the classes.
+const LANGUAGE_ATTRS: [&str; 3] = ["lang", "data-lang", "data-language"];
+
+/// Classes that mean no language, like Prism's `language-none`.
+const NO_LANGUAGES: [&str; 2] = ["none", "nohighlight"];
+
+/// Ancestors of a `pre` that are searched for language classes, like
+/// `<div class="highlight-python"><div class="highlight"><pre>`.
+const LANGUAGE_ANCESTORS: usize = 3;
+
+/// Classes of line number elements in highlighted code: Pygments, Rouge, Prism, and highlight.js.
+const LINE_NUMBER_CLASSES: [&str; 7] = [
+    "linenos",
+    "lineno",
+    "linenodiv",
+    "gutter",
+    "rouge-gutter",
+    "line-numbers-rows",
+    "hljs-ln-numbers",
+];
+
+/// Classes of highlighted code blocks without a language, on the `pre` or its parent:
+/// Pygments, highlight.js, Chroma, Pandoc, Prettify, Prism, and Rouge.
+const HIGHLIGHTER_CLASSES: [&str; 7] = [
+    "highlight",
+    "hljs",
+    "chroma",
+    "sourceCode",
+    "prettyprint",
+    "line-numbers",
+    "rouge-code",
+];
+
+/// Classes of the tables that Pygments and Rouge put line numbers and code in.
+const CODE_TABLE_CLASSES: [&str; 2] = ["highlighttable", "rouge-table"];
+
+pub(crate) fn apply_code_options(
+    builder: HtmlToMarkdownBuilder,
+    options: CodeOptions,
+) -> HtmlToMarkdownBuilder {
+    let table_options = options.clone();
+    builder
+        .add_handler(
+            vec!["pre"],
+            move |handlers: &dyn Handlers, element: Element| {
+                pre_handler(handlers, element, &options)
+            },
+        )
+        .add_handler(
+            vec!["table"],
+            move |handlers: &dyn Handlers, element: Element| {
+                code_table_handler(handlers, element, &table_options)
+            },
+        )
+}
+
+fn pre_handler(
+    handlers: &dyn Handlers,
+    element: Element,
+    options: &CodeOptions,
+) -> Option<HandlerResult> {
+    // Other `pre`s are preformatted text that can have links and emphasis
+    let language = find_language(element.node, element.node, options);
+    if language.is_none() && !has_code_child(element.node) && !is_highlighted(element.node) {
+        return handlers.fallback(element);
+    }
+    let mut code = CodeText::default();
+    code.read(element.node);
+    if code.text.trim().is_empty() {
+        return handlers.fallback(element);
+    }
+
+    let text = match (options.line_numbers, code.first_line_number) {
+        (true, Some(first)) => number_lines(&code.text, first),
+        _ => code.text,
+    };
+    Some(code_block(handlers, &text, language.as_deref()).into())
+}
+
+/// Pygments and Rouge tables with line numbers in one cell and the code in the other.
+fn code_table_handler(
+    handlers: &dyn Handlers,
+    element: Element,
+    options: &CodeOptions,
+) -> Option<HandlerResult> {
+    let is_code_table = node_attr(element.node, "class").is_some_and(|class| {
+        class
+            .split_whitespace()
+            .any(|class| CODE_TABLE_CLASSES.contains(&class))
+    });
+    if !is_code_table {
+        return handlers.fallback(element);
+    }
+    let mut pres = vec![];
+    find_pres(element.node, &mut pres);
+    let [numbers, code_pre] = &pres[..] else {
+        return handlers.fallback(element);
+    };
+
+    let mut line_numbers = CodeText::default();
+    line_numbers.read(numbers);
+    let first_line_number = line_numbers
+        .text
+        .split_whitespace()
+        .next()
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(1);
+
+    let mut code = CodeText::default();
+    code.read(code_pre);
+    let language = find_language(code_pre, element.node, options);
+    let text = if options.line_numbers {
+        number_lines(&code.text, first_line_number)
+    } else {
+        code.text
+    };
+    Some(code_block(handlers, &text, language.as_deref()).into())
+}
+
+fn has_code_child(pre: &Rc<Node>) -> bool {
+    pre.children
+        .borrow()
+        .iter()
+        .any(|child| tag_name(child) == Some("code"))
+}
+
+fn is_highlighted(pre: &Rc<Node>) -> bool {
+    [Some(Rc::clone(pre)), parent_node(pre)]
+        .into_iter()
+        .flatten()
+        .any(|node| {
+            node_attr(&node, "class").is_some_and(|class| {
+                class
+                    .split_whitespace()
+                    .any(|class| HIGHLIGHTER_CLASSES.contains(&class))
+            })
+        })
+}
+
+fn find_pres(node: &Rc<Node>, pres: &mut Vec<Rc<Node>>) {
+    for child in node.children.borrow().iter() {
+        if tag_name(child) == Some("pre") {
+            pres.push(Rc::clone(child));
+        } else {
+            find_pres(child, pres);
+        }
+    }
+}
+
+/// The text of a code block, without highlighting markup and line numbers.
+#[derive(Default)]
+struct CodeText {
+    text: String,
+    /// The number of the first line, if the code has line numbers.
+    first_line_number: Option<usize>,
+}
+
+impl CodeText {
+    fn read(&mut self, node: &Rc<Node>) {
+        self.read_children(node);
+        if self.text.ends_with('\n') {
+            self.text.pop();
+        }
+    }
+
+    fn read_children(&mut self, node: &Rc<Node>) {
+        for child in node.children.borrow().iter() {
+            match &child.data {
+                NodeData::Text { contents } => self.text.push_str(&contents.borrow()),
+                NodeData::Element { .. } => self.read_element(child),
+                _ => {}
+            }
+        }
+    }
+
+    fn read_element(&mut self, node: &Rc<Node>) {
+        if is_line_number(node) {
+            if self.first_line_number.is_none() {
+                let mut numbers = CodeText::default();
+                numbers.read_children(node);
+                self.first_line_number = node_attr(node, "data-line-number")
+                    .or(Some(numbers.text))
+                    .and_then(|number| number.split_whitespace().next()?.parse().ok())
+                    .or(Some(1));
+            }
+            return;
+        }
+        match tag_name(node) {
+            Some("br") => self.text.push('\n'),
+            // highlight.js puts every line in a table row
+            Some("tr") => {
+                self.read_children(node);
+                if !self.text.ends_with('\n') {
+                    self.text.push('\n');
+                }
+            }
+            _ => self.read_children(node),
+        }
+    }
+}
+
+fn is_line_number(node: &Rc<Node>) -> bool {
+    node_attr(node, "class").is_some_and(|class| {
+        class
+            .split_whitespace()
+            .any(|class| LINE_NUMBER_CLASSES.contains(&class))
+    })
+}
+
+/// Prefix lines with their numbers, aligned to the right.
+fn number_lines(text: &str, first: usize) -> String {
+    let lines = text.lines().collect::<Vec<&str>>();
+    let width = (first + lines.len().saturating_sub(1)).to_string().len();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| format!("{:>width$}  {}", first + index, line, width = width))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The language of a code block from the attributes of the `code` and `pre`, then
+/// the classes of their ancestors.
+fn find_language(pre: &Rc<Node>, block: &Rc<Node>, options: &CodeOptions) -> Option<String> {
+    let code = pre
+        .children
+        .borrow()
+        .iter()
+        .find(|child| tag_name(child) == Some("code"))
+        .cloned();
+    // Only the code and the `pre` have language attributes, `lang` of ancestors is
+    // the language of the text
+    let mut nodes = code
+        .into_iter()
+        .chain([Rc::clone(pre)])
+        .map(|node| (node, true))
+        .collect::<Vec<(Rc<Node>, bool)>>();
+    if !Rc::ptr_eq(pre, block) {
+        nodes.push((Rc::clone(block), false));
+    }
+    let mut ancestor = parent_node(block);
+    for _ in 0..LANGUAGE_ANCESTORS {
+        let Some(node) = ancestor else {
+            break;
+        };
+        ancestor = parent_node(&node);
+        nodes.push((node, false));
+    }
+
+    nodes.iter().find_map(|(node, has_language_attrs)| {
+        let attr_language = has_language_attrs
+            .then(|| LANGUAGE_ATTRS.iter().find_map(|attr| node_attr(node, attr)))
+            .flatten();
+        let language = attr_language.or_else(|| {
+            let class = node_attr(node, "class")?;
+            class.split_whitespace().find_map(|class| {
+                options
+                    .language_classes
+                    .iter()
+                    .find_map(|pattern| match_class(pattern, class))
+                    .map(|language| language.to_string())
+            })
+        })?;
+        let language = language.trim();
+        (!language.is_empty() && !NO_LANGUAGES.contains(&language)).then(|| language.to_string())
+    })
+}
+
+/// The part of a class that matches the `*` of a pattern.
+fn match_class<'a>(pattern: &str, class: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once('*')?;
+    let language = class.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    (!language.is_empty()).then_some(language)
+}
+
+/// A fenced or indented code block, by the code block options.
+fn code_block(handlers: &dyn Handlers, text: &str, language: Option<&str>) -> String {
+    let options = handlers.options();
+    let block = if options.code_block_style == CodeBlockStyle::Fenced {
+        let fence_char = match options.code_block_fence {
+            CodeBlockFence::Backticks => '`',
+            CodeBlockFence::Tildes => '~',
+        };
+        // The fence is longer than any run of fence chars in the code
+        let longest_run = text
+            .split(|c| c != fence_char)
+            .map(|run| run.len())
+            .max()
+            .unwrap_or(0);
+        let fence = fence_char.to_string().repeat((longest_run + 1).max(3));
+        format!("{}{}\n{}\n{}", fence, language.unwrap_or(""), text, fence)
+    } else {
+        text.lines()
+            .map(|line| {
+                if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("    {}", line)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    format!("\n\n{}\n\n", block)
+}
@@ -0,0 +1,36 @@
+use std::io::{self, Write};
+
+use clap::{value_parser, Arg, ArgMatches, Command};
+use clap_complete::{generate, Shell};
+use clap_mangen::Man;
+
+pub(crate) fn completions_command() -> Command {
+    Command::new("completions")
+        .about("Print the shell completion script")
+        .arg(
+            Arg::new("shell")
+                .help("Shell to complete for")
+                .required(true)
+                .value_parser(value_parser!(Shell)),
+        )
+}
+
+pub(crate) fn man_command() -> Command {
+    Command::new("man").about("Print the man page in roff format")
+}
+
+/// Print the completion script of `cmd`, e.g. `htmd completions bash > /etc/bash_completion.d/htmd`.
+pub(crate) fn print_completions(matches: &ArgMatches, mut cmd: Command) {
+    let shell = *matches.get_one::<Shell>("shell").unwrap();
+    let bin_name = cmd.get_name().to_string();
+    generate(shell, &mut cmd, bin_name, &mut io::stdout().lock());
+}
+
+/// Print the man page of `cmd`, e.g. `htmd man > htmd.1`.
+pub(crate) fn print_man_page(cmd: Command) {
+    let mut out = io::stdout().lock();
+    Man::new(cmd)
+        .render(&mut out)
+        .and_then(|_| out.flush())
+        .expect("Failed to write to stdout");
+}
@@ -0,0 +1,95 @@
+use std::{error::Error, path::Path};
+
+use htmd::{options::Options, HtmlToMarkdown};
+
+use crate::{
+    code_util::{apply_code_options, CodeOptions},
+    config_util::{read_cli_options_from_file, read_cli_options_from_toml, OptionsFormat},
+    flavor_util::{apply_flavor, Flavor},
+    math_util::{apply_math, MathStyle},
+    table_util::{apply_table_options, TableOptions},
+};
+
+/// Conversion options, the same as the cli options and the `[options]` table of an options file.
+pub struct Config {
+    pub converter_options: Options,
+    /// Tags to skip, like `script` and `style`.
+    pub ignored_tags: Option<Vec<String>>,
+    /// Write all output files into the output directory, without sub directories.
+    pub flatten_output: bool,
+    pub scripting_enabled: bool,
+    pub flavor: Flavor,
+    pub table_options: TableOptions,
+    pub code_options: CodeOptions,
+    pub math: MathStyle,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            converter_options: Options::default(),
+            ignored_tags: None,
+            flatten_output: false,
+            scripting_enabled: true,
+            flavor: Flavor::Default,
+            table_options: TableOptions::default(),
+            code_options: CodeOptions::default(),
+            math: MathStyle::None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the `[options]` table of an options file.
+    ///
+    /// ```
+    /// let config = htmd_cli::Config::from_toml("[options]\nheading-style = \"setex\"").unwrap();
+    /// let converter = config.into_converter();
+    /// assert_eq!(converter.convert("<h1>Hi</h1>").unwrap(), "Hi\n==");
+    /// ```
+    pub fn from_toml(text: &str) -> Result<Config, Box<dyn Error>> {
+        read_cli_options_from_toml(text)
+    }
+
+    /// Read a TOML options file, see [Config::from_toml].
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Config, Box<dyn Error>> {
+        read_cli_options_from_file(path.as_ref(), Some(OptionsFormat::Toml))
+    }
+
+    /// Read a TOML, YAML, or JSON options file by its extension.
+    ///
+    /// YAML and JSON files have the same `options` table, like `{"options": {"heading-style": "setex"}}`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Config, Box<dyn Error>> {
+        read_cli_options_from_file(path.as_ref(), None)
+    }
+
+    /// Build the converter of these options.
+    pub fn into_converter(self) -> HtmlToMarkdown {
+        let Config {
+            converter_options,
+            ignored_tags,
+            scripting_enabled,
+            flavor,
+            table_options,
+            code_options,
+            math,
+            ..
+        } = self;
+
+        let mut builder = HtmlToMarkdown::builder()
+            .options(converter_options)
+            .scripting_enabled(scripting_enabled);
+
+        // Flavor handlers are added first so ignored tags still take precedence
+        builder = apply_flavor(builder, flavor);
+        builder = apply_table_options(builder, table_options, flavor);
+        // After the tables, so tables of highlighted code are code blocks
+        builder = apply_code_options(builder, code_options);
+        builder = apply_math(builder, math);
+
+        if let Some(ignored_tags) = ignored_tags {
+            builder = builder.skip_tags(ignored_tags.iter().map(|tag| tag.as_str()).collect());
+        }
+        builder.build()
+    }
+}
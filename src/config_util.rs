@@ -1,12 +1,27 @@
-use std::{error::Error, fmt, fs};
+use std::{error::Error, fmt, fs, path::Path, process::exit};
 
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
 use htmd::options::{
     BrStyle, BulletListMarker, CodeBlockFence, CodeBlockStyle, HeadingStyle, HrStyle,
     LinkReferenceStyle, LinkStyle, Options,
 };
-use toml::Value;
+use serde::Deserialize;
+use toml::{map::Map, Value};
 
-use crate::cli_options::CliOptions;
+use crate::{
+    code_util::CodeOptions,
+    config::Config,
+    flavor_util::{flavor_config, Flavor},
+    math_util::MathStyle,
+    option_util::{
+        deserialize_value, did_you_mean, options_file_schema, profile_config, OptionKind,
+        OptionValue, OPTIONS, PROFILES,
+    },
+    table_util::{TableFallback, TableOptions, TableSpan},
+};
+
+/// File written by `htmd config init`.
+const DEFAULT_CONFIG_FILE: &str = ".htmd.toml";
 
 #[derive(Debug)]
 pub(crate) struct ParseConfigError {
@@ -29,239 +44,540 @@ impl fmt::Display for ParseConfigError {
 
 impl Error for ParseConfigError {}
 
-pub(crate) fn read_cli_options_from_toml_file(
-    filepath: &str,
-) -> Result<CliOptions, Box<dyn Error>> {
-    let text = fs::read_to_string(filepath)?;
-    let value: Value = toml::from_str(&text)?;
-
-    let Some(options) = value.get("options") else {
-        return Err(parse_config_err("No [options] in the config file."));
-    };
-
-    let converter_options = read_converter_options(options)?;
-    let ignored_tags = read_ignored_tags(options)?;
-    let flatten_output = options
-        .get("flatten-output")
-        .map(|value| value.as_bool().unwrap_or(false))
-        .unwrap_or(false);
-    let scripting_enabled = options
-        .get("scripting-enabled")
-        .map(|value| value.as_bool().unwrap_or(true))
-        .unwrap_or(false);
-
-    let options = CliOptions {
-        converter_options,
-        ignored_tags,
-        flatten_output,
-        scripting_enabled,
+pub(crate) fn config_command() -> Command {
+    let file_arg = |help: &'static str| {
+        Arg::new("file")
+            .help(help)
+            .num_args(1)
+            .value_hint(ValueHint::FilePath)
     };
+    Command::new("config")
+        .about("Create, show, or validate options files, or print their schema")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("init")
+                .about("Write an options file that lists and explains every option")
+                .arg(
+                    file_arg("File to write; defaults to .htmd.toml")
+                        .default_value(DEFAULT_CONFIG_FILE),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .help("Options to start from")
+                        .num_args(1)
+                        .default_value("default")
+                        .value_parser(PROFILES),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Overwrite the file if it exists")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Print the options an options file resolves to, including defaults")
+                .arg(file_arg("Options file; defaults to the default options"))
+                .arg(options_format_arg()),
+        )
+        .subcommand(
+            Command::new("schema").about("Print the JSON Schema of options files, for editors"),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check an options file, exits with 1 if it is invalid")
+                .arg(file_arg("Options file").required(true))
+                .arg(options_format_arg()),
+        )
+}
 
-    Ok(options)
+pub(crate) fn run_config_command(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("init", init_matches)) => {
+            let file = Path::new(init_matches.get_one::<String>("file").unwrap());
+            if file.exists() && !init_matches.get_flag("force") {
+                eprintln!("{:?} already exists, use --force to overwrite it", file);
+                exit(1);
+            }
+            let profile = init_matches.get_one::<String>("profile").unwrap();
+            fs::write(file, options_file_template(profile)).unwrap_or_else(|e| {
+                eprintln!("Cannot write file {:?}: {}", file, e);
+                exit(1);
+            });
+            println!("Wrote {:?}", file);
+        }
+        Some(("show", show_matches)) => {
+            let config = match show_matches.get_one::<String>("file") {
+                Some(file) => read_options_file_or_exit(Path::new(file), show_matches),
+                None => Config::default(),
+            };
+            print!("{}", options_file_text(&config));
+        }
+        Some(("schema", _)) => {
+            let schema =
+                serde_json::to_string_pretty(&options_file_schema()).expect("Schema is valid JSON");
+            println!("{}", schema);
+        }
+        Some(("validate", validate_matches)) => {
+            let file = Path::new(validate_matches.get_one::<String>("file").unwrap());
+            read_options_file_or_exit(file, validate_matches);
+            println!("{:?} is valid", file);
+        }
+        _ => unreachable!("config requires a subcommand"),
+    }
 }
 
-fn read_converter_options(options: &Value) -> Result<Options, Box<dyn Error>> {
-    let default_options = Options::default();
-
-    let heading_style = map_options_str_field(options, "heading-style", |value| match value {
-        None => Ok(default_options.heading_style),
-        Some("atx") => Ok(HeadingStyle::Atx),
-        Some("setex") => Ok(HeadingStyle::Setex),
-        _ => Err(parse_config_err(format!(
-            "Unknown heading-style value '{:?}'",
-            value.unwrap()
-        ))),
-    })?;
-
-    let hr_style = map_options_str_field(options, "hr-style", |value| match value {
-        None => Ok(default_options.hr_style),
-        Some("asterisks") => Ok(HrStyle::Asterisks),
-        Some("dashes") => Ok(HrStyle::Dashes),
-        Some("underscores") => Ok(HrStyle::Underscores),
-        _ => Err(parse_config_err(format!(
-            "Unknown hr-style value '{:?}'",
-            value.unwrap()
-        ))),
-    })?;
-
-    let br_style = map_options_str_field(options, "br-style", |value| match value {
-        None => Ok(default_options.br_style),
-        Some("two-spaces") => Ok(BrStyle::TwoSpaces),
-        Some("backslash") => Ok(BrStyle::Backslash),
-        _ => Err(parse_config_err(format!(
-            "Unknown br-style value '{:?}'",
-            value.unwrap()
-        ))),
-    })?;
-
-    let link_style = map_options_str_field(options, "link-style", |value| match value {
-        None => Ok(default_options.link_style),
-        Some("inlined") => Ok(LinkStyle::Inlined),
-        Some("inlined-prefer-autolinks") => Ok(LinkStyle::InlinedPreferAutolinks),
-        Some("referenced") => Ok(LinkStyle::Referenced),
-        _ => Err(parse_config_err(format!(
-            "Unknown link-style value '{:?}'",
-            value.unwrap()
-        ))),
-    })?;
-
-    let link_reference_style =
-        map_options_str_field(options, "link-reference-style", |value| match value {
-            None => Ok(default_options.link_reference_style),
-            Some("full") => Ok(LinkReferenceStyle::Full),
-            Some("collapsed") => Ok(LinkReferenceStyle::Collapsed),
-            Some("shortcut") => Ok(LinkReferenceStyle::Shortcut),
-            _ => Err(parse_config_err(format!(
-                "Unknown link-reference-style value '{:?}'",
-                value.unwrap()
-            ))),
-        })?;
-
-    let code_block_style =
-        map_options_str_field(options, "code-block-style", |value| match value {
-            None => Ok(default_options.code_block_style),
-            Some("fenced") => Ok(CodeBlockStyle::Fenced),
-            Some("indented") => Ok(CodeBlockStyle::Indented),
-            _ => Err(parse_config_err(format!(
-                "Unknown code-block-style value '{:?}'",
-                value.unwrap()
-            ))),
-        })?;
-
-    let code_block_fence =
-        map_options_str_field(options, "code-block-fence", |value| match value {
-            None => Ok(default_options.code_block_fence),
-            Some("backticks") => Ok(CodeBlockFence::Backticks),
-            Some("tildes") => Ok(CodeBlockFence::Tildes),
-            _ => Err(parse_config_err(format!(
-                "Unknown code-block-fence value '{:?}'",
-                value.unwrap()
-            ))),
-        })?;
-
-    let bullet_list_marker =
-        map_options_str_field(options, "bullet-list-marker", |value| match value {
-            None => Ok(default_options.bullet_list_marker),
-            Some("asterisk") => Ok(BulletListMarker::Asterisk),
-            Some("dash") => Ok(BulletListMarker::Dash),
-            _ => Err(parse_config_err(format!(
-                "Unknown bullet-list-marker value '{:?}'",
-                value.unwrap()
-            ))),
-        })?;
-
-    let preformatted_code =
-        map_options_bool_field(options, "preformatted-code", |value| match value {
-            None => Ok(default_options.preformatted_code),
-            Some(false) => Ok(false),
-            Some(true) => Ok(true),
-        })?;
-
-    let ul_bullet_spacing =
-        map_options_u8_field(options, "ul-bullet-spacing", |value| match value {
-            None => Ok(default_options.ul_bullet_spacing),
-            Some(val) => Ok(val),
-        })?;
-
-    let ol_number_spacing =
-        map_options_u8_field(options, "ol-number-spacing", |value| match value {
-            None => Ok(default_options.ol_number_spacing),
-            Some(val) => Ok(val),
-        })?;
-
-    let options = Options {
-        heading_style,
-        hr_style,
-        br_style,
-        link_style,
-        link_reference_style,
-        code_block_style,
-        code_block_fence,
-        bullet_list_marker,
-        ul_bullet_spacing,
-        ol_number_spacing,
-        preformatted_code,
-        translation_mode: htmd::options::TranslationMode::Pure,
-    };
+fn read_options_file_or_exit(file: &Path, matches: &ArgMatches) -> Config {
+    read_cli_options_from_file(file, resolve_options_format(matches)).unwrap_or_else(|e| {
+        eprintln!("Invalid options file {:?}: {}", file, e);
+        exit(1);
+    })
+}
 
-    Ok(options)
+/// An options file with every option of `config`.
+fn options_file_text(config: &Config) -> String {
+    let mut file = Map::new();
+    file.insert("options".to_string(), write_cli_options(config));
+    toml::to_string(&file).expect("Options are always valid TOML")
 }
 
-fn read_ignored_tags(options: &Value) -> Result<Option<Vec<String>>, Box<dyn Error>> {
-    let Some(value) = options.get("ignored-tags") else {
-        return Ok(None);
+/// An options file of a profile, every option is commented with its values.
+fn options_file_template(profile: &str) -> String {
+    let Value::Table(values) = write_cli_options(&profile_config(profile)) else {
+        unreachable!("Options are written to a table");
     };
-    let Some(array) = value.as_array() else {
-        return Err(parse_config_err(
-            "options.ignored-tags must be an string array",
-        ));
+    let Value::Table(defaults) = write_cli_options(&Config::default()) else {
+        unreachable!("Options are written to a table");
     };
-    let mut tags: Vec<String> = vec![];
-    for tag in array {
-        let Some(tag) = tag.as_str() else {
-            return Err(parse_config_err(
-                "Non-string element found in options.ignored-tags",
-            ));
+
+    let mut text = format!(
+        "# htmd options, created by `htmd config init --profile {}`\n\
+        # Use it with `htmd --options-file <file>`, HTMD_* variables and cli flags\n\
+        # take precedence over it.\n\n\
+        [options]\n",
+        profile
+    );
+    for option in &OPTIONS {
+        text.push('\n');
+        for line in option.help.lines() {
+            text.push_str(&format!("# {}\n", line.trim()));
+        }
+        let allowed = match option.kind {
+            OptionKind::Value(names) => names()
+                .iter()
+                .map(|name| format!("\"{}\"", name))
+                .collect::<Vec<String>>()
+                .join(", "),
+            OptionKind::Bool => "true, false".to_string(),
+            OptionKind::U8 => "0 to 255".to_string(),
+            OptionKind::List => "a list of strings".to_string(),
         };
-        tags.push(tag.to_string());
+        let default = defaults
+            .get(option.name)
+            .map_or("none".to_string(), |value| value.to_string());
+        text.push_str(&format!("# Values: {}; default: {}\n", allowed, default));
+        match values.get(option.name) {
+            Some(value) => text.push_str(&format!("{} = {}\n", option.name, value)),
+            None => text.push_str(&format!("# {} = [\"script\", \"style\"]\n", option.name)),
+        }
     }
-    Ok(Some(tags))
+    text
 }
 
-fn map_options_str_field<F, R>(options: &Value, name: &str, map_fn: F) -> Result<R, Box<dyn Error>>
-where
-    F: FnOnce(Option<&str>) -> Result<R, Box<dyn Error>>,
-{
-    if let Some(value) = options.get(name) {
-        let Some(value) = value.as_str() else {
-            return Err(parse_config_err(format!(
-                "options.{} must be a string",
-                name
-            )));
-        };
-        map_fn(Some(value))
-    } else {
-        map_fn(None)
+/// Write options to a table, the reverse of [read_cli_options].
+pub(crate) fn write_cli_options(config: &Config) -> Value {
+    let options = &config.converter_options;
+    let str_value = |value: &str| Value::String(value.to_string());
+
+    let mut table = Map::new();
+    table.insert(
+        "heading-style".to_string(),
+        str_value(options.heading_style.name()),
+    );
+    table.insert("hr-style".to_string(), str_value(options.hr_style.name()));
+    table.insert("br-style".to_string(), str_value(options.br_style.name()));
+    table.insert(
+        "link-style".to_string(),
+        str_value(options.link_style.name()),
+    );
+    table.insert(
+        "link-reference-style".to_string(),
+        str_value(options.link_reference_style.name()),
+    );
+    table.insert(
+        "code-block-style".to_string(),
+        str_value(options.code_block_style.name()),
+    );
+    table.insert(
+        "code-block-fence".to_string(),
+        str_value(options.code_block_fence.name()),
+    );
+    let code_options = &config.code_options;
+    table.insert(
+        "code-language-classes".to_string(),
+        Value::Array(
+            code_options
+                .language_classes
+                .iter()
+                .map(|pattern| str_value(pattern))
+                .collect(),
+        ),
+    );
+    table.insert(
+        "code-line-numbers".to_string(),
+        Value::Boolean(code_options.line_numbers),
+    );
+    table.insert(
+        "bullet-list-marker".to_string(),
+        str_value(options.bullet_list_marker.name()),
+    );
+    table.insert(
+        "preformatted-code".to_string(),
+        Value::Boolean(options.preformatted_code),
+    );
+    table.insert(
+        "ul-bullet-spacing".to_string(),
+        Value::Integer(options.ul_bullet_spacing.into()),
+    );
+    table.insert(
+        "ol-number-spacing".to_string(),
+        Value::Integer(options.ol_number_spacing.into()),
+    );
+    if let Some(ignored_tags) = &config.ignored_tags {
+        table.insert(
+            "ignored-tags".to_string(),
+            Value::Array(ignored_tags.iter().map(|tag| str_value(tag)).collect()),
+        );
     }
+    table.insert(
+        "flatten-output".to_string(),
+        Value::Boolean(config.flatten_output),
+    );
+    table.insert(
+        "scripting-enabled".to_string(),
+        Value::Boolean(config.scripting_enabled),
+    );
+    table.insert("flavor".to_string(), str_value(config.flavor.name()));
+    let table_options = &config.table_options;
+    table.insert(
+        "table-fallback".to_string(),
+        str_value(table_options.fallback.name()),
+    );
+    table.insert(
+        "table-span".to_string(),
+        str_value(table_options.span.name()),
+    );
+    table.insert(
+        "table-alignment".to_string(),
+        Value::Boolean(table_options.alignment),
+    );
+    table.insert(
+        "table-padding".to_string(),
+        Value::Boolean(table_options.padding),
+    );
+    table.insert("math".to_string(), str_value(config.math.name()));
+    Value::Table(table)
 }
 
-fn map_options_bool_field<F, R>(options: &Value, name: &str, map_fn: F) -> Result<R, Box<dyn Error>>
-where
-    F: FnOnce(Option<bool>) -> Result<R, Box<dyn Error>>,
-{
-    if let Some(value) = options.get(name) {
-        let Some(value) = value.as_bool() else {
-            return Err(parse_config_err(format!(
-                "options.{} must be a boolean",
-                name
-            )));
-        };
-        map_fn(Some(value))
-    } else {
-        map_fn(None)
+/// The `[options]` table, keys are the same as the cli option names.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct OptionsTable {
+    #[serde(default, deserialize_with = "deserialize_value")]
+    heading_style: Option<HeadingStyle>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    hr_style: Option<HrStyle>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    br_style: Option<BrStyle>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    link_style: Option<LinkStyle>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    link_reference_style: Option<LinkReferenceStyle>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    code_block_style: Option<CodeBlockStyle>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    code_block_fence: Option<CodeBlockFence>,
+    code_language_classes: Option<Vec<String>>,
+    code_line_numbers: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    bullet_list_marker: Option<BulletListMarker>,
+    preformatted_code: Option<bool>,
+    ul_bullet_spacing: Option<u8>,
+    ol_number_spacing: Option<u8>,
+    ignored_tags: Option<Vec<String>>,
+    flatten_output: Option<bool>,
+    scripting_enabled: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    flavor: Option<Flavor>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    table_fallback: Option<TableFallback>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    table_span: Option<TableSpan>,
+    table_alignment: Option<bool>,
+    table_padding: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_value")]
+    math: Option<MathStyle>,
+}
+
+/// Formats of options files.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum OptionsFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl OptionsFormat {
+    /// The format of a file by its extension, TOML for other extensions.
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => OptionsFormat::Yaml,
+            Some("json") => OptionsFormat::Json,
+            _ => OptionsFormat::Toml,
+        }
     }
 }
 
-fn map_options_u8_field<F, R>(options: &Value, name: &str, map_fn: F) -> Result<R, Box<dyn Error>>
-where
-    F: FnOnce(Option<u8>) -> Result<R, Box<dyn Error>>,
-{
-    if let Some(value) = options.get(name) {
-        let Some(value) = value.as_integer() else {
-            return Err(parse_config_err(format!(
-                "options.{} must be an u8 integer",
-                name
-            )));
-        };
-        map_fn(Some(value.try_into().unwrap()))
-    } else {
-        map_fn(None)
+pub(crate) fn options_format_arg() -> Arg {
+    Arg::new("options-format")
+        .long("options-format")
+        .help("Format of the options file; defaults to the file extension, or toml")
+        .num_args(1)
+        .value_parser(OptionsFormat::names())
+}
+
+/// Read `--options-format`.
+pub(crate) fn resolve_options_format(matches: &ArgMatches) -> Option<OptionsFormat> {
+    matches
+        .get_one::<String>("options-format")
+        .and_then(|name| OptionsFormat::parse(name))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OptionsFile {
+    options: Option<OptionsTable>,
+}
+
+impl OptionsTable {
+    /// Missing options fall back to the defaults of the flavor, see [flavor_config].
+    fn into_config(self) -> Config {
+        let Config {
+            converter_options: default_options,
+            flatten_output,
+            scripting_enabled,
+            flavor,
+            table_options,
+            code_options,
+            math,
+            ..
+        } = flavor_config(self.flavor.unwrap_or(Flavor::Default));
+
+        Config {
+            converter_options: Options {
+                heading_style: self.heading_style.unwrap_or(default_options.heading_style),
+                hr_style: self.hr_style.unwrap_or(default_options.hr_style),
+                br_style: self.br_style.unwrap_or(default_options.br_style),
+                link_style: self.link_style.unwrap_or(default_options.link_style),
+                link_reference_style: self
+                    .link_reference_style
+                    .unwrap_or(default_options.link_reference_style),
+                code_block_style: self
+                    .code_block_style
+                    .unwrap_or(default_options.code_block_style),
+                code_block_fence: self
+                    .code_block_fence
+                    .unwrap_or(default_options.code_block_fence),
+                bullet_list_marker: self
+                    .bullet_list_marker
+                    .unwrap_or(default_options.bullet_list_marker),
+                ul_bullet_spacing: self
+                    .ul_bullet_spacing
+                    .unwrap_or(default_options.ul_bullet_spacing),
+                ol_number_spacing: self
+                    .ol_number_spacing
+                    .unwrap_or(default_options.ol_number_spacing),
+                preformatted_code: self
+                    .preformatted_code
+                    .unwrap_or(default_options.preformatted_code),
+                translation_mode: default_options.translation_mode,
+            },
+            ignored_tags: self.ignored_tags,
+            flatten_output: self.flatten_output.unwrap_or(flatten_output),
+            scripting_enabled: self.scripting_enabled.unwrap_or(scripting_enabled),
+            flavor: self.flavor.unwrap_or(flavor),
+            table_options: TableOptions {
+                fallback: self.table_fallback.unwrap_or(table_options.fallback),
+                span: self.table_span.unwrap_or(table_options.span),
+                alignment: self.table_alignment.unwrap_or(table_options.alignment),
+                padding: self.table_padding.unwrap_or(table_options.padding),
+            },
+            code_options: CodeOptions {
+                language_classes: self
+                    .code_language_classes
+                    .unwrap_or(code_options.language_classes),
+                line_numbers: self.code_line_numbers.unwrap_or(code_options.line_numbers),
+            },
+            math: self.math.unwrap_or(math),
+        }
+    }
+}
+
+/// Read an options file, the format is taken from the extension unless it is given.
+pub(crate) fn read_cli_options_from_file(
+    filepath: &Path,
+    format: Option<OptionsFormat>,
+) -> Result<Config, Box<dyn Error>> {
+    let text = fs::read_to_string(filepath)?;
+    let format = format.unwrap_or_else(|| OptionsFormat::from_path(filepath));
+    read_cli_options_from_str(&text, format)
+}
+
+/// Read only the options that an options file sets, after checking the file.
+pub(crate) fn read_options_value_from_file(
+    filepath: &Path,
+    format: Option<OptionsFormat>,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    let text = fs::read_to_string(filepath)?;
+    let format = format.unwrap_or_else(|| OptionsFormat::from_path(filepath));
+    read_cli_options_from_str(&text, format)?;
+    let file: serde_json::Value = match format {
+        OptionsFormat::Toml => toml::from_str(&text)?,
+        OptionsFormat::Yaml => serde_yaml::from_str(&text)?,
+        OptionsFormat::Json => serde_json::from_str(&text)?,
+    };
+    Ok(file["options"].clone())
+}
+
+pub(crate) fn read_cli_options_from_toml(text: &str) -> Result<Config, Box<dyn Error>> {
+    read_cli_options_from_str(text, OptionsFormat::Toml)
+}
+
+/// Read the `options` table of an options file, unknown keys are errors.
+pub(crate) fn read_cli_options_from_str(
+    text: &str,
+    format: OptionsFormat,
+) -> Result<Config, Box<dyn Error>> {
+    let file: OptionsFile = match format {
+        OptionsFormat::Toml => toml::from_str(text).map_err(|e| toml_error(text, &e))?,
+        OptionsFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+            let position = e
+                .location()
+                .map(|location| (location.line(), location.column()));
+            // Errors in the table are prefixed with its key
+            let message = e.to_string();
+            located_error(message.trim_start_matches("options: "), position)
+        })?,
+        OptionsFormat::Json => serde_json::from_str(text)
+            .map_err(|e| located_error(&e.to_string(), Some((e.line(), e.column()))))?,
+    };
+
+    let Some(options) = file.options else {
+        return Err(parse_config_err("No options in the options file."));
+    };
+
+    Ok(options.into_config())
+}
+
+/// Read options from a JSON object, keys are the same as the cli option names.
+pub(crate) fn read_cli_options_from_json(
+    options: &serde_json::Value,
+) -> Result<Config, Box<dyn Error>> {
+    match options {
+        serde_json::Value::Null => Ok(Config::default()),
+        serde_json::Value::Object(_) => OptionsTable::deserialize(options)
+            .map(OptionsTable::into_config)
+            .map_err(|e| parse_config_err(explain(&e.to_string())).into()),
+        _ => Err(parse_config_err("options must be an object")),
     }
 }
 
+/// Prefix the error with its line and column in `text`.
+fn toml_error(text: &str, error: &toml::de::Error) -> Box<ParseConfigError> {
+    let message = explain(error.message());
+    let Some(span) = error.span() else {
+        return parse_config_err(message);
+    };
+    let before = &text[..span.start];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count() + 1;
+    let column = before[line_start..].chars().count() + 1;
+    parse_config_err(format!("line {}, column {}: {}", line, column, message))
+}
+
+/// Move the position of a YAML or JSON error to the front, like [toml_error].
+fn located_error(message: &str, position: Option<(usize, usize)>) -> Box<ParseConfigError> {
+    let Some((line, column)) = position else {
+        return parse_config_err(explain(message));
+    };
+    let message = message
+        .strip_suffix(&format!(" at line {} column {}", line, column))
+        .unwrap_or(message);
+    parse_config_err(format!(
+        "line {}, column {}: {}",
+        line,
+        column,
+        explain(message)
+    ))
+}
+
+/// Replace the list of expected keys of an unknown key error with a suggestion.
+fn explain(message: &str) -> String {
+    // YAML errors are prefixed with the path of the key
+    let Some(name) = message
+        .split_once("unknown field `")
+        .and_then(|(_, rest)| rest.split('`').next())
+    else {
+        return message.trim_end().to_string();
+    };
+    let mut candidates = OPTIONS.iter().map(|option| option.name).collect::<Vec<_>>();
+    candidates.push("options");
+    format!("unknown key `{}`{}", name, did_you_mean(name, &candidates))
+}
+
 fn parse_config_err<S>(message: S) -> Box<ParseConfigError>
 where
     S: AsRef<str>,
 {
     Box::new(ParseConfigError::new(message.as_ref()))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    /// `OPTIONS`, the `[options]` table, and the written options must have the same keys,
+    /// cli options are read through the table.
+    #[test]
+    fn options_are_in_sync() {
+        let options = OPTIONS
+            .iter()
+            .map(|option| option.name.to_string())
+            .collect::<BTreeSet<String>>();
+
+        // Serde lists every field of the table in the error of an unknown key
+        let error = serde_json::from_value::<OptionsTable>(serde_json::json!({ "?": 0 }))
+            .err()
+            .unwrap()
+            .to_string();
+        let (_, fields) = error.split_once("expected one of ").unwrap();
+        let table_keys = fields
+            .split(", ")
+            .map(|field| field.trim_matches('`').to_string())
+            .collect::<BTreeSet<String>>();
+        assert_eq!(options, table_keys);
+
+        let config = Config {
+            ignored_tags: Some(vec![]),
+            ..Config::default()
+        };
+        let Value::Table(written) = write_cli_options(&config) else {
+            unreachable!("Options are written to a table");
+        };
+        assert_eq!(options, written.keys().cloned().collect());
+    }
+}
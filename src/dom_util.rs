@@ -0,0 +1,35 @@
+use html5ever::{parse_document, tendril::TendrilSink, ParseOpts};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+
+/// Parse a document, or the start of one, with html5ever.
+pub(crate) fn parse_html(html: &str) -> Result<RcDom, String> {
+    parse_document(RcDom::default(), ParseOpts::default())
+        .from_utf8()
+        .read_from(&mut html.as_bytes())
+        .map_err(|e| format!("Failed to parse html: {}", e))
+}
+
+/// Visit the elements of `dom` with their nesting depth, top level elements are at depth 1.
+///
+/// The walk stops at the first error of `visit`. It does not recurse, so deeply nested
+/// documents cannot overflow the stack.
+pub(crate) fn walk_elements(
+    dom: &RcDom,
+    mut visit: impl FnMut(&Handle, usize) -> Result<(), String>,
+) -> Result<(), String> {
+    // `dom` must outlive the walk, dropping a node detaches the children of all its descendants.
+    let mut stack = vec![(dom.document.clone(), 1)];
+    while let Some((node, depth)) = stack.pop() {
+        let child_depth = match node.data {
+            NodeData::Element { .. } => {
+                visit(&node, depth)?;
+                depth + 1
+            }
+            _ => depth,
+        };
+        for child in node.children.borrow().iter() {
+            stack.push((child.clone(), child_depth));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,480 @@
+use std::rc::Rc;
+
+use htmd::{
+    element_handler::{HandlerResult, Handlers},
+    options::{BrStyle, CodeBlockFence, CodeBlockStyle},
+    Element, HtmlToMarkdownBuilder,
+};
+use html5ever::serialize::{serialize, SerializeOpts, TraversalScope};
+use markup5ever_rcdom::{Node, NodeData, SerializableHandle};
+
+use crate::{config::Config, table_util::TableFallback};
+
+/// The Markdown flavor of the output.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Flavor {
+    Default,
+    /// Wiki links, embedded images, and callouts for Obsidian and Foam.
+    Obsidian,
+    /// CommonMark, anything without a CommonMark syntax is kept as raw HTML.
+    CommonMark,
+    /// GitHub Flavored Markdown: pipe tables, strikethrough, task lists, and footnotes.
+    Gfm,
+    /// MultiMarkdown: pipe tables, footnotes, and definition lists.
+    MultiMarkdown,
+    /// PHP Markdown Extra: pipe tables, footnotes, and definition lists.
+    PhpExtra,
+    /// The original Markdown.pl syntax, without fenced code blocks.
+    Strict,
+}
+
+impl Flavor {
+    /// The flavors that are Markdown dialects, other flavors keep the htmd output.
+    fn is_dialect(self) -> bool {
+        !matches!(self, Flavor::Default | Flavor::Obsidian)
+    }
+
+    /// CommonMark and Markdown.pl have no tables, every table is written with the table fallback.
+    pub(crate) fn has_pipe_tables(self) -> bool {
+        !matches!(self, Flavor::CommonMark | Flavor::Strict)
+    }
+
+    fn has_footnotes(self) -> bool {
+        matches!(self, Flavor::Gfm | Flavor::MultiMarkdown | Flavor::PhpExtra)
+    }
+
+    fn has_definition_lists(self) -> bool {
+        matches!(self, Flavor::MultiMarkdown | Flavor::PhpExtra)
+    }
+}
+
+/// The defaults of a flavor, options that are set explicitly take precedence.
+pub(crate) fn flavor_config(flavor: Flavor) -> Config {
+    let mut config = Config {
+        flavor,
+        ..Config::default()
+    };
+    let options = &mut config.converter_options;
+    match flavor {
+        // Markdown.pl has no fenced code blocks or backslash line breaks
+        Flavor::Strict => {
+            options.code_block_style = CodeBlockStyle::Indented;
+            options.br_style = BrStyle::TwoSpaces;
+        }
+        // Markdown Extra fences code with tildes, backticks only came with later versions
+        Flavor::PhpExtra => {
+            options.code_block_fence = CodeBlockFence::Tildes;
+            options.br_style = BrStyle::TwoSpaces;
+        }
+        Flavor::MultiMarkdown => options.br_style = BrStyle::TwoSpaces,
+        _ => {}
+    }
+    // Dialects keep the tables they cannot write as HTML, like the other raw HTML fallbacks
+    if flavor.is_dialect() {
+        config.table_options.fallback = TableFallback::Html;
+    }
+    config
+}
+
+/// Inline tags without a Markdown syntax, dialects keep them as raw HTML.
+const RAW_INLINE_TAGS: [&str; 7] = ["sub", "sup", "ins", "u", "mark", "kbd", "abbr"];
+
+/// Strikethrough tags, GFM writes them as `~~text~~`.
+const STRIKETHROUGH_TAGS: [&str; 3] = ["del", "s", "strike"];
+
+/// Admonition classes that are converted to callouts, the class is used as the callout type.
+const CALLOUT_CLASSES: [&str; 10] = [
+    "note",
+    "tip",
+    "info",
+    "important",
+    "warning",
+    "caution",
+    "danger",
+    "error",
+    "success",
+    "example",
+];
+
+pub(crate) fn apply_flavor(
+    builder: HtmlToMarkdownBuilder,
+    flavor: Flavor,
+) -> HtmlToMarkdownBuilder {
+    match flavor {
+        Flavor::Default => builder,
+        Flavor::Obsidian => builder
+            .add_handler(vec!["a"], wiki_link_handler)
+            .add_handler(vec!["img"], wiki_embed_handler)
+            .add_handler(vec!["div", "aside"], callout_handler),
+        _ => apply_dialect(builder, flavor),
+    }
+}
+
+/// Add the handlers of a Markdown dialect, what a dialect has no syntax for is kept as raw HTML.
+fn apply_dialect(builder: HtmlToMarkdownBuilder, flavor: Flavor) -> HtmlToMarkdownBuilder {
+    debug_assert!(flavor.is_dialect());
+    let mut builder = builder.add_handler(RAW_INLINE_TAGS.to_vec(), raw_inline_handler);
+
+    builder = if flavor == Flavor::Gfm {
+        builder
+            .add_handler(STRIKETHROUGH_TAGS.to_vec(), strikethrough_handler)
+            .add_handler(vec!["input"], task_marker_handler)
+    } else {
+        builder
+            .add_handler(STRIKETHROUGH_TAGS.to_vec(), raw_inline_handler)
+            .add_handler(vec!["input"], raw_checkbox_handler)
+    };
+
+    builder = if flavor.has_definition_lists() {
+        builder.add_handler(vec!["dl"], definition_list_handler)
+    } else {
+        builder.add_handler(vec!["dl"], raw_block_handler)
+    };
+
+    if flavor.has_footnotes() {
+        builder = builder
+            .add_handler(vec!["sup"], footnote_ref_handler)
+            .add_handler(vec!["a"], footnote_backref_handler)
+            .add_handler(vec!["section", "div", "aside"], footnotes_handler);
+    }
+    builder
+}
+
+fn raw_inline_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let content = handlers.walk_children(element.node).content;
+    Some(format!("<{tag}>{}</{tag}>", content, tag = element.tag).into())
+}
+
+fn raw_block_handler(_handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    Some(format!("\n\n{}\n\n", raw_html(element.node)).into())
+}
+
+fn strikethrough_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let content = handlers.walk_children(element.node).content;
+    let text = content.trim();
+    if text.is_empty() {
+        return Some(content.into());
+    }
+    // Keep the surrounding spaces outside, `~~ text~~` is not a strikethrough
+    let start = &content[..content.len() - content.trim_start().len()];
+    let end = &content[content.trim_end().len()..];
+    Some(format!("{}~~{}~~{}", start, text, end).into())
+}
+
+fn task_marker_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    match checkbox_state(&element) {
+        Some(true) => Some("[x] ".into()),
+        Some(false) => Some("[ ] ".into()),
+        None => handlers.fallback(element),
+    }
+}
+
+fn raw_checkbox_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    match checkbox_state(&element) {
+        Some(true) => Some("<input type=\"checkbox\" checked disabled> ".into()),
+        Some(false) => Some("<input type=\"checkbox\" disabled> ".into()),
+        None => handlers.fallback(element),
+    }
+}
+
+/// Whether a checkbox is checked, `None` for other elements.
+fn checkbox_state(element: &Element) -> Option<bool> {
+    let is_checkbox =
+        get_attr(element, "type").is_some_and(|kind| kind.eq_ignore_ascii_case("checkbox"));
+    is_checkbox.then(|| get_attr(element, "checked").is_some())
+}
+
+/// Definition lists of MultiMarkdown and Markdown Extra, a term followed by `:   definition`.
+fn definition_list_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let mut list = String::new();
+    let mut last_tag = None;
+    let children = element.node.children.borrow();
+    for child in children.iter() {
+        let tag = tag_name(child);
+        match tag {
+            Some("dt") => {
+                let term = handlers.walk_children(child).content;
+                let term = term.split_whitespace().collect::<Vec<&str>>().join(" ");
+                // A term after a definition starts a new group
+                list.push_str(match last_tag {
+                    Some("dd") => "\n\n",
+                    Some(_) => "\n",
+                    None => "",
+                });
+                list.push_str(&term);
+            }
+            Some("dd") => {
+                let definition = handlers.walk_children(child).content;
+                if last_tag.is_some() {
+                    list.push('\n');
+                }
+                list.push(':');
+                for (index, line) in definition.trim_matches('\n').lines().enumerate() {
+                    if index > 0 {
+                        list.push('\n');
+                    }
+                    if !line.is_empty() {
+                        list.push_str(if index == 0 { "   " } else { "    " });
+                        list.push_str(line);
+                    }
+                }
+            }
+            _ => continue,
+        }
+        last_tag = tag;
+    }
+    if last_tag.is_none() {
+        return handlers.fallback(element);
+    }
+    Some(format!("\n\n{}\n\n", list).into())
+}
+
+/// A footnote reference like `<sup><a href="#fn1">1</a></sup>` becomes `[^1]`.
+fn footnote_ref_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let label = element
+        .node
+        .children
+        .borrow()
+        .iter()
+        .filter(|child| tag_name(child) == Some("a"))
+        .find_map(|link| node_attr(link, "href"))
+        .and_then(|href| footnote_label(href.strip_prefix('#')?, FOOTNOTE_PREFIX));
+    match label {
+        Some(label) => Some(format!("[^{}]", label).into()),
+        None => handlers.fallback(element),
+    }
+}
+
+/// The `↩` links from a footnote back to its reference are dropped.
+fn footnote_backref_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let is_backref = get_attr(&element, "href")
+        .and_then(|href| footnote_label(href.strip_prefix('#')?, FOOTNOTE_REF_PREFIX))
+        .is_some();
+    if is_backref {
+        Some("".into())
+    } else {
+        handlers.fallback(element)
+    }
+}
+
+/// The footnotes section, its items become `[^1]: text`.
+fn footnotes_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let class = get_attr(&element, "class").unwrap_or_default();
+    let is_footnotes = class
+        .split_whitespace()
+        .any(|class| class == "footnotes" || class == "footnote")
+        || get_attr(&element, "data-footnotes").is_some()
+        || get_attr(&element, "role").as_deref() == Some("doc-endnotes");
+    if !is_footnotes {
+        return handlers.fallback(element);
+    }
+
+    let mut items = vec![];
+    collect_footnotes(element.node, &mut items);
+    if items.is_empty() {
+        return handlers.fallback(element);
+    }
+
+    let mut footnotes = String::new();
+    for (label, item) in items {
+        let content = handlers.walk_children(&item).content;
+        if !footnotes.is_empty() {
+            footnotes.push_str("\n\n");
+        }
+        footnotes.push_str(&format!("[^{}]:", label));
+        // Following lines are indented to stay in the footnote
+        for (index, line) in content.trim_matches('\n').trim_end().lines().enumerate() {
+            if index > 0 {
+                footnotes.push('\n');
+            }
+            if !line.is_empty() {
+                footnotes.push_str(if index == 0 { " " } else { "    " });
+                footnotes.push_str(line.trim_start_matches(|c| index == 0 && c == ' '));
+            }
+        }
+    }
+    Some(format!("\n\n{}\n\n", footnotes).into())
+}
+
+/// The `li` items of a footnotes section with their labels.
+fn collect_footnotes(node: &Rc<Node>, items: &mut Vec<(String, Rc<Node>)>) {
+    for child in node.children.borrow().iter() {
+        let label = node_attr(child, "id").and_then(|id| footnote_label(&id, FOOTNOTE_PREFIX));
+        match (tag_name(child), label) {
+            (Some("li"), Some(label)) => items.push((label, Rc::clone(child))),
+            _ => collect_footnotes(child, items),
+        }
+    }
+}
+
+/// Id prefix of footnotes, like `fn1` (Pandoc), `fn:1` (kramdown), or `user-content-fn-1` (GitHub).
+const FOOTNOTE_PREFIX: &str = "fn";
+
+/// Id prefix of footnote references, the targets of the links back from footnotes.
+const FOOTNOTE_REF_PREFIX: &str = "fnref";
+
+/// The label of a footnote id with the prefix, e.g. `1` of `fn:1`.
+fn footnote_label(id: &str, prefix: &str) -> Option<String> {
+    let id = id.strip_prefix("user-content-").unwrap_or(id);
+    let rest = id.strip_prefix(prefix)?;
+    if prefix == FOOTNOTE_PREFIX && rest.starts_with("ref") {
+        return None;
+    }
+    let label = rest.trim_start_matches([':', '-', '_']);
+    (!label.is_empty() && !label.contains(char::is_whitespace)).then(|| decode_percent(label))
+}
+
+/// The element as HTML, for what the flavor has no syntax for.
+pub(crate) fn raw_html(node: &Rc<Node>) -> String {
+    let mut bytes = vec![];
+    let opts = SerializeOpts {
+        traversal_scope: TraversalScope::IncludeNode,
+        ..Default::default()
+    };
+    serialize(&mut bytes, &SerializableHandle::from(Rc::clone(node)), opts)
+        .expect("Writing to a Vec doesn't fail");
+    let html = String::from_utf8_lossy(&bytes);
+
+    // A blank line ends an HTML block, so blank lines are kept as character references
+    let mut block = String::new();
+    for line in html.trim().lines() {
+        if line.trim().is_empty() {
+            block.push_str("&#10;");
+        } else {
+            if !block.is_empty() {
+                block.push('\n');
+            }
+            block.push_str(line);
+        }
+    }
+    block
+}
+
+fn wiki_link_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let Some((page, heading)) = get_attr(&element, "href").and_then(|href| internal_page(&href))
+    else {
+        return handlers.fallback(element);
+    };
+
+    let content = handlers.walk_children(element.node).content;
+    let text = content.trim();
+
+    let mut target = page.clone();
+    if let Some(heading) = &heading {
+        target.push('#');
+        target.push_str(heading);
+    }
+    if text.is_empty() || (heading.is_none() && text == page) {
+        Some(format!("[[{}]]", target).into())
+    } else {
+        Some(format!("[[{}|{}]]", target, text.replace('\n', " ")).into())
+    }
+}
+
+fn wiki_embed_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let Some(src) = get_attr(&element, "src").filter(|src| is_relative_link(src)) else {
+        return handlers.fallback(element);
+    };
+    let path = src.split(['?', '#']).next().unwrap_or(&src);
+    let filename = path.rsplit('/').next().unwrap_or(path);
+    Some(format!("![[{}]]", decode_percent(filename)).into())
+}
+
+fn callout_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let class = get_attr(&element, "class").unwrap_or_default();
+    let Some(callout_type) = class
+        .split_whitespace()
+        .find_map(|class| CALLOUT_CLASSES.iter().find(|name| **name == class))
+    else {
+        return handlers.fallback(element);
+    };
+
+    let content = handlers.walk_children(element.node).content;
+    let mut callout = format!("\n\n> [!{}]", callout_type);
+    for line in content.trim_matches('\n').lines() {
+        callout.push_str("\n>");
+        if !line.is_empty() {
+            callout.push(' ');
+            callout.push_str(line);
+        }
+    }
+    callout.push_str("\n\n");
+    Some(callout.into())
+}
+
+/// Resolve a link to a converted page, returns the page name and the heading.
+fn internal_page(href: &str) -> Option<(String, Option<String>)> {
+    if !is_relative_link(href) {
+        return None;
+    }
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let path = path.split('?').next().unwrap_or(path);
+    let filename = path.rsplit('/').next().unwrap_or(path);
+    let lower = filename.to_ascii_lowercase();
+    let stem = [".html", ".htm"]
+        .iter()
+        .find(|ext| lower.ends_with(*ext))
+        .map(|ext| &filename[..filename.len() - ext.len()])?;
+    let heading = fragment
+        .filter(|fragment| !fragment.is_empty())
+        .map(|fragment| decode_percent(fragment).replace(['-', '_'], " "));
+    Some((decode_percent(stem), heading))
+}
+
+fn is_relative_link(link: &str) -> bool {
+    !link.is_empty() && !link.starts_with(['/', '#']) && !link.contains(':')
+}
+
+fn get_attr(element: &Element, name: &str) -> Option<String> {
+    element
+        .attrs
+        .iter()
+        .find(|attr| &attr.name.local == name)
+        .map(|attr| attr.value.to_string())
+}
+
+pub(crate) fn node_attr(node: &Rc<Node>, name: &str) -> Option<String> {
+    let NodeData::Element { attrs, .. } = &node.data else {
+        return None;
+    };
+    let attrs = attrs.borrow();
+    attrs
+        .iter()
+        .find(|attr| &attr.name.local == name)
+        .map(|attr| attr.value.to_string())
+}
+
+pub(crate) fn tag_name(node: &Rc<Node>) -> Option<&str> {
+    match &node.data {
+        NodeData::Element { name, .. } => Some(name.local.as_ref()),
+        _ => None,
+    }
+}
+
+pub(crate) fn parent_node(node: &Rc<Node>) -> Option<Rc<Node>> {
+    // The parent is a `Cell`, so it is taken out and put back
+    let parent = node.parent.take();
+    let upgraded = parent.as_ref().and_then(|parent| parent.upgrade());
+    node.parent.set(parent);
+    upgraded
+}
+
+pub(crate) fn decode_percent(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = text.get(index + 1..index + 3);
+        if let (b'%', Some(Ok(byte))) = (bytes[index], hex.map(|hex| u8::from_str_radix(hex, 16))) {
+            decoded.push(byte);
+            index += 3;
+            continue;
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
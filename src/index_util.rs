@@ -0,0 +1,214 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ArgMatches;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum IndexMode {
+    /// Write a README.md into every output directory.
+    Dir,
+    /// Write one mdBook SUMMARY.md into the output directory.
+    Summary,
+}
+
+/// Titles are looked for in the start of a page, where the head is.
+const TITLE_SEARCH_LIMIT: usize = 64 * 1024;
+
+/// A converted page, used to build indexes.
+#[derive(Debug)]
+pub struct Page {
+    pub output: PathBuf,
+    /// Only read when the batch needs titles, see [crate::BatchConverter::titles].
+    pub title: Option<String>,
+}
+
+pub(crate) fn resolve_index_mode(matches: &ArgMatches) -> Option<IndexMode> {
+    let mode = matches.get_one::<String>("index")?;
+    match mode.as_str() {
+        "summary" => Some(IndexMode::Summary),
+        _ => Some(IndexMode::Dir),
+    }
+}
+
+/// Use the `<title>` of the page, or the first heading of the converted Markdown.
+pub(crate) fn page_title(html_title: Option<String>, md: &str, file: &Path) -> String {
+    html_title
+        .or_else(|| markdown_heading(md))
+        .unwrap_or_else(|| file.file_stem().unwrap().to_string_lossy().to_string())
+}
+
+pub(crate) fn write_index(output: &Path, pages: &[Page], mode: IndexMode) {
+    let mut tree = DirNode::default();
+    for page in pages {
+        let relative = page.output.strip_prefix(output).unwrap_or(&page.output);
+        tree.insert(relative, page.title.as_deref().unwrap_or_default());
+    }
+
+    match mode {
+        IndexMode::Dir => write_dir_indexes(output, &tree, None),
+        IndexMode::Summary => {
+            let mut summary = String::from("# Summary\n\n");
+            write_summary_entries(&mut summary, &tree, Path::new(""), 0);
+            let summary_file = output.join("SUMMARY.md");
+            fs::write(&summary_file, summary)
+                .unwrap_or_else(|_| panic!("Cannot write file: {:?}", summary_file));
+        }
+    }
+}
+
+#[derive(Default)]
+struct DirNode {
+    /// File name to page title.
+    pages: BTreeMap<String, String>,
+    dirs: BTreeMap<String, DirNode>,
+}
+
+impl DirNode {
+    fn insert(&mut self, relative: &Path, title: &str) {
+        let mut node = self;
+        if let Some(parent) = relative.parent() {
+            for component in parent.components() {
+                let name = component.as_os_str().to_string_lossy().to_string();
+                node = node.dirs.entry(name).or_default();
+            }
+        }
+        let filename = relative.file_name().unwrap().to_string_lossy().to_string();
+        node.pages.insert(filename, title.to_string());
+    }
+
+    /// The page that represents this directory, like `index.md` or `README.md`.
+    fn index_page(&self) -> Option<&str> {
+        ["index.md", "README.md", "_index.md"]
+            .into_iter()
+            .find(|name| self.pages.contains_key(*name))
+    }
+}
+
+fn write_dir_indexes(dir: &Path, node: &DirNode, name: Option<&str>) {
+    let index_file = dir.join("README.md");
+    if node.pages.contains_key("README.md") {
+        eprintln!(
+            "Skip writing index, a converted page already exists: {:?}",
+            index_file
+        );
+    } else {
+        let mut index = format!("# {}\n\n", name.unwrap_or("Index"));
+        for (filename, title) in &node.pages {
+            index.push_str(&format!(
+                "- [{}]({})\n",
+                link_text(title),
+                link_path(filename)
+            ));
+        }
+        for dir_name in node.dirs.keys() {
+            index.push_str(&format!(
+                "- [{}]({}/README.md)\n",
+                dir_name,
+                link_path(dir_name)
+            ));
+        }
+        fs::write(&index_file, index)
+            .unwrap_or_else(|_| panic!("Cannot write file: {:?}", index_file));
+    }
+
+    for (dir_name, child) in &node.dirs {
+        write_dir_indexes(&dir.join(dir_name), child, Some(dir_name));
+    }
+}
+
+fn write_summary_entries(summary: &mut String, node: &DirNode, dir: &Path, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let index_page = node.index_page();
+    for (filename, title) in &node.pages {
+        if depth > 0 && index_page == Some(filename.as_str()) {
+            // Already linked by the chapter of this directory
+            continue;
+        }
+        let path = dir.join(filename);
+        summary.push_str(&format!(
+            "{}- [{}]({})\n",
+            indent,
+            link_text(title),
+            link_path(&path.to_string_lossy())
+        ));
+    }
+    for (dir_name, child) in &node.dirs {
+        let child_dir = dir.join(dir_name);
+        // Directories without an index page become mdBook draft chapters
+        // Chapters take the title of their index page
+        let (title, link) = match child.index_page() {
+            Some(page) => (
+                link_text(&child.pages[page]),
+                link_path(&child_dir.join(page).to_string_lossy()),
+            ),
+            None => (dir_name.clone(), String::new()),
+        };
+        summary.push_str(&format!("{}- [{}]({})\n", indent, title, link));
+        write_summary_entries(summary, child, &child_dir, depth + 1);
+    }
+}
+
+fn link_text(text: &str) -> String {
+    text.replace('[', "\\[").replace(']', "\\]")
+}
+
+fn link_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    if path.contains(' ') {
+        format!("<{}>", path)
+    } else {
+        path
+    }
+}
+
+/// The `<title>` of a page.
+pub(crate) fn html_title(html: &str) -> Option<String> {
+    let mut end = html.len().min(TITLE_SEARCH_LIMIT);
+    while !html.is_char_boundary(end) {
+        end -= 1;
+    }
+    let html = &html[..end];
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = decode_entities(html[start..end].trim());
+    let title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+fn markdown_heading(md: &str) -> Option<String> {
+    let lines = md.lines().collect::<Vec<&str>>();
+    for (index, line) in lines.iter().enumerate() {
+        if let Some(heading) = line.strip_prefix('#') {
+            let heading = heading.trim_start_matches('#').trim();
+            if !heading.is_empty() {
+                return Some(heading.to_string());
+            }
+        }
+        let underline = lines.get(index + 1).map(|next| next.trim());
+        if let Some(next) = underline {
+            let is_setext = !next.is_empty() && next.chars().all(|ch| ch == '=' || ch == '-');
+            if is_setext && !line.trim().is_empty() {
+                return Some(line.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
@@ -1,13 +1,23 @@
 use std::{
-    fs,
-    io::{self, Read},
-    path::PathBuf,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
     process::exit,
 };
 
 use clap::ArgMatches;
 use glob::glob;
 
+use crate::{
+    cli_options::{is_from_env, is_overridden},
+    index_util::{resolve_index_mode, IndexMode},
+    layout_util::{resolve_layout, Layout},
+    limit_util::{resolve_convert_limits, ConvertLimits},
+    merge_util::{resolve_merge_order, MergeOrder},
+    pool_util::resolve_jobs,
+    progress_util::{resolve_verbosity, Verbosity},
+};
+
 #[derive(PartialEq)]
 pub(crate) enum Input {
     Stdin(String),
@@ -18,27 +28,63 @@ pub(crate) enum Input {
 pub(crate) enum Output {
     Stdout,
     Fs(PathBuf),
+    /// Merge all converted files into one Markdown file.
+    Merge(PathBuf),
+}
+
+#[derive(PartialEq)]
+pub(crate) enum StdoutMode {
+    /// Join documents with blank lines.
+    Concat,
+    /// Put a separator line with the source path before each document.
+    Delimited(String),
+    /// Write one `{"path": ..., "markdown": ...}` JSON object per line.
+    Ndjson,
+}
+
+/// Options about how converted files are written.
+pub(crate) struct OutputOptions {
+    pub stdout_mode: Option<StdoutMode>,
+    pub merge_order: MergeOrder,
+    pub index_mode: Option<IndexMode>,
+    pub layout: Option<Layout>,
+}
+
+/// Limits the size of every HTML input.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct InputLimit {
+    pub max_size: Option<u64>,
+    /// Truncate oversized inputs instead of rejecting them.
+    pub truncate: bool,
 }
 
-pub(crate) fn resolve_input(matches: &ArgMatches) -> Input {
+/// Options about how the conversion runs.
+pub(crate) struct RunOptions {
+    pub jobs: usize,
+    pub input_limit: InputLimit,
+    pub verbosity: Verbosity,
+    pub convert_limits: ConvertLimits,
+    /// Let pages override options with `<meta name="htmd:...">` tags.
+    pub honor_meta_options: bool,
+}
+
+pub(crate) fn resolve_input(matches: &ArgMatches, limit: InputLimit) -> Input {
     let read_stdin = || -> Input {
-        let mut text = String::new();
-        io::stdin()
-            .read_to_string(&mut text)
-            .expect("Cannot read text from stdin.");
+        let text = read_html(io::stdin().lock(), limit, "stdin").unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(1);
+        });
         Input::Stdin(text)
     };
 
     let input_from_arg = |id: &str| -> Option<Input> {
-        let Some(input_arg) = matches.get_one::<String>(id) else {
-            return None;
-        };
+        let input_arg = matches.get_one::<String>(id)?;
 
         if input_arg == "-" {
             return Some(read_stdin());
         }
 
-        let files = get_html_files_from_input(&input_arg);
+        let files = get_html_files_from_input(input_arg);
 
         if files.is_empty() {
             eprintln!("File or directory does not exists: {}", input_arg);
@@ -48,8 +94,16 @@ pub(crate) fn resolve_input(matches: &ArgMatches) -> Input {
         Some(Input::Fs(files))
     };
 
-    if let Some(input) = input_from_arg("input") {
-        return input;
+    // A positional input takes precedence over HTMD_INPUT, an empty variable is no input
+    let ignore_env_input = is_from_env(matches, "input")
+        && (matches.contains_id("input-unnamed")
+            || matches
+                .get_one::<String>("input")
+                .is_some_and(String::is_empty));
+    if !ignore_env_input {
+        if let Some(input) = input_from_arg("input") {
+            return input;
+        }
     }
 
     if let Some(input) = input_from_arg("input-unnamed") {
@@ -60,6 +114,11 @@ pub(crate) fn resolve_input(matches: &ArgMatches) -> Input {
 }
 
 pub(crate) fn resolve_output(matches: &ArgMatches) -> Output {
+    if let Some(merge) = matches.get_one::<String>("merge") {
+        if !is_overridden(matches, "merge") {
+            return Output::Merge(PathBuf::from(merge));
+        }
+    }
     let Some(output) = matches.get_one::<String>("output") else {
         return Output::Stdout;
     };
@@ -69,6 +128,127 @@ pub(crate) fn resolve_output(matches: &ArgMatches) -> Output {
     Output::Fs(PathBuf::from(output))
 }
 
+pub(crate) fn resolve_output_options(matches: &ArgMatches) -> OutputOptions {
+    OutputOptions {
+        stdout_mode: resolve_stdout_mode(matches),
+        merge_order: resolve_merge_order(matches),
+        index_mode: resolve_index_mode(matches),
+        layout: resolve_layout(matches),
+    }
+}
+
+pub(crate) fn resolve_run_options(matches: &ArgMatches) -> RunOptions {
+    RunOptions {
+        jobs: resolve_jobs(matches),
+        input_limit: InputLimit {
+            max_size: matches.get_one::<u64>("max-input-size").copied(),
+            truncate: matches.get_one::<String>("oversize").unwrap() == "truncate",
+        },
+        verbosity: resolve_verbosity(matches),
+        convert_limits: resolve_convert_limits(matches),
+        honor_meta_options: matches.get_flag("honor-meta-options"),
+    }
+}
+
+/// Read an HTML file, the error message contains the file path.
+pub(crate) fn read_html_file(file: &Path, limit: InputLimit) -> io::Result<String> {
+    let name = file.to_string_lossy();
+    let reader = File::open(file)
+        .map_err(|e| io::Error::new(e.kind(), format!("Cannot read file {:?}: {}", name, e)))?;
+    read_html(reader, limit, &name)
+}
+
+/// Read HTML into one String, reading stops once the input exceeds the size limit.
+fn read_html<R: Read>(reader: R, limit: InputLimit, name: &str) -> io::Result<String> {
+    let mut reader = BufReader::new(reader);
+    let mut bytes: Vec<u8> = vec![];
+    match limit.max_size {
+        Some(max_size) => {
+            // One more byte tells an oversized input from one of exactly the limit
+            let read_limit = max_size.saturating_add(1);
+            reader.by_ref().take(read_limit).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 > max_size {
+                if !limit.truncate {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Input {} is larger than --max-input-size ({} bytes), \
+                            use '--oversize truncate' to convert the beginning of it",
+                            name, max_size
+                        ),
+                    ));
+                }
+                eprintln!("Input {} is truncated to {} bytes", name, max_size);
+                bytes.truncate(max_size as usize);
+            }
+        }
+        None => {
+            reader.read_to_end(&mut bytes)?;
+        }
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        // A truncated input may end in the middle of a character
+        Err(e) if limit.truncate && e.utf8_error().error_len().is_none() => {
+            let valid_up_to = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid_up_to);
+            Ok(String::from_utf8(bytes).unwrap())
+        }
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Cannot read {} as UTF-8 text", name),
+        )),
+    }
+}
+
+/// Write Markdown through a buffered writer, `front_matter` is written first.
+pub(crate) fn write_markdown(file: &Path, front_matter: Option<&str>, md: &str) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(file)?);
+    if let Some(front_matter) = front_matter {
+        writer.write_all(front_matter.as_bytes())?;
+    }
+    writer.write_all(md.as_bytes())?;
+    writer.flush()
+}
+
+/// Parse sizes like `1048576`, `512K`, `100M` or `1G`.
+pub(crate) fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let number = upper.trim_end_matches(['B', 'I']);
+    let (number, unit) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1024),
+        Some('M') => (&number[..number.len() - 1], 1024 * 1024),
+        Some('G') => (&number[..number.len() - 1], 1024 * 1024 * 1024),
+        _ => (number, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| {
+            format!(
+                "invalid size '{}', expected e.g. 1048576, 512K, 100M or 1G",
+                value
+            )
+        })
+}
+
+fn resolve_stdout_mode(matches: &ArgMatches) -> Option<StdoutMode> {
+    let mode = matches.get_one::<String>("stdout-mode")?;
+    match mode.as_str() {
+        "delimited" => {
+            let delimiter = matches.get_one::<String>("stdout-delimiter").unwrap();
+            Some(StdoutMode::Delimited(delimiter.to_string()))
+        }
+        "ndjson" => Some(StdoutMode::Ndjson),
+        _ => Some(StdoutMode::Concat),
+    }
+}
+
 fn get_html_files_from_input(pattern: &str) -> Vec<PathBuf> {
     if pattern == "." || pattern == "./" {
         // Fast path for the current dir
@@ -111,18 +291,16 @@ fn get_html_files_from_input(pattern: &str) -> Vec<PathBuf> {
 
 fn read_dir_html_files(dir: &PathBuf) -> Vec<PathBuf> {
     let mut files: Vec<PathBuf> = Vec::new();
-    for entry in fs::read_dir(dir).unwrap() {
-        if let Ok(entry) = entry {
-            let child = entry.path();
-            if possible_html_file(&child) {
-                files.push(child);
-            }
+    for entry in fs::read_dir(dir).unwrap().flatten() {
+        let child = entry.path();
+        if possible_html_file(&child) {
+            files.push(child);
         }
     }
     files
 }
 
-fn possible_html_file(path: &PathBuf) -> bool {
+fn possible_html_file(path: &Path) -> bool {
     let Some(ext) = path.extension() else {
         return false;
     };
@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ArgMatches;
+
+use crate::{
+    index_util::{write_index, IndexMode, Page},
+    merge_util::rewrite_links,
+    path_util::relative_path,
+};
+
+/// Static site generator layouts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layout {
+    Hugo,
+    Docusaurus,
+    Mdbook,
+    Mkdocs,
+}
+
+/// Front matter fields of a converted page, except for the title.
+pub(crate) struct LayoutEntry {
+    pub layout: Layout,
+    /// 1-based position of the page in its output directory.
+    pub position: usize,
+    pub slug: String,
+}
+
+pub(crate) fn resolve_layout(matches: &ArgMatches) -> Option<Layout> {
+    let layout = matches.get_one::<String>("layout")?;
+    match layout.as_str() {
+        "hugo" => Some(Layout::Hugo),
+        "docusaurus" => Some(Layout::Docusaurus),
+        "mdbook" => Some(Layout::Mdbook),
+        _ => Some(Layout::Mkdocs),
+    }
+}
+
+impl Layout {
+    /// The Markdown filename for an input file stem, `index.html` becomes the section index.
+    pub fn output_filename(&self, file_stem: &str) -> String {
+        if !file_stem.eq_ignore_ascii_case("index") {
+            return format!("{}.md", file_stem);
+        }
+        match self {
+            Layout::Hugo => "_index.md".to_string(),
+            Layout::Mdbook => "README.md".to_string(),
+            Layout::Docusaurus | Layout::Mkdocs => "index.md".to_string(),
+        }
+    }
+
+    /// Write the navigation file of the layout, if the generator needs one.
+    pub fn write_nav(&self, output: &Path, pages: &[Page]) {
+        match self {
+            Layout::Mdbook => write_index(output, pages, IndexMode::Summary),
+            Layout::Mkdocs => write_mkdocs_nav(output, pages),
+            Layout::Hugo | Layout::Docusaurus => {}
+        }
+    }
+}
+
+impl LayoutEntry {
+    pub fn front_matter(&self, title: &str) -> Option<String> {
+        let title = serde_json::Value::from(title);
+        let fields = match self.layout {
+            Layout::Hugo => format!("title: {}\nweight: {}\n", title, self.position),
+            Layout::Docusaurus => format!(
+                "title: {}\nsidebar_position: {}\nslug: {}\n",
+                title,
+                self.position,
+                serde_json::Value::from(self.slug.as_str())
+            ),
+            Layout::Mkdocs => format!("title: {}\n", title),
+            Layout::Mdbook => return None,
+        };
+        Some(format!("---\n{}---\n\n", fields))
+    }
+}
+
+/// Compute the front matter fields for every output file, `outputs` are in input order.
+pub(crate) fn plan_layout(layout: Layout, output: &Path, outputs: &[PathBuf]) -> Vec<LayoutEntry> {
+    let mut siblings: HashMap<&Path, Vec<&PathBuf>> = HashMap::new();
+    for file in outputs {
+        siblings
+            .entry(file.parent().unwrap_or(Path::new("")))
+            .or_default()
+            .push(file);
+    }
+    for files in siblings.values_mut() {
+        // Section indexes go first, the rest follows the path order
+        files.sort_by_key(|file| (!is_index_file(file), file.to_path_buf()));
+    }
+
+    outputs
+        .iter()
+        .map(|file| {
+            let position = siblings[file.parent().unwrap_or(Path::new(""))]
+                .iter()
+                .position(|sibling| *sibling == file)
+                .unwrap()
+                + 1;
+            LayoutEntry {
+                layout,
+                position,
+                slug: slug_of(file.strip_prefix(output).unwrap_or(file)),
+            }
+        })
+        .collect()
+}
+
+/// Point links between converted pages to their Markdown files, `outputs` maps the
+/// normalized input files to their output files.
+pub(crate) fn rewrite_page_links(
+    md: &str,
+    input: &Path,
+    output: &Path,
+    outputs: &HashMap<PathBuf, PathBuf>,
+) -> String {
+    let input_dir = input.parent().unwrap_or(Path::new(""));
+    let output_dir = output.parent().unwrap_or(Path::new(""));
+    rewrite_links(md, input_dir, |target, fragment| {
+        let link = relative_path(output_dir, outputs.get(target)?);
+        let link = link.to_string_lossy().replace('\\', "/");
+        if fragment.is_empty() {
+            Some(link)
+        } else {
+            Some(format!("{}#{}", link, fragment))
+        }
+    })
+}
+
+fn is_index_file(file: &Path) -> bool {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    stem == "_index" || stem == "index" || stem == "README"
+}
+
+fn slug_of(relative: &Path) -> String {
+    let path = if is_index_file(relative) {
+        relative.parent().unwrap_or(Path::new("")).to_path_buf()
+    } else {
+        relative.with_extension("")
+    };
+    let path = path.to_string_lossy().replace('\\', "/");
+    format!("/{}", path)
+}
+
+fn write_mkdocs_nav(output: &Path, pages: &[Page]) {
+    let mut pages = pages
+        .iter()
+        .map(|page| {
+            let relative = page.output.strip_prefix(output).unwrap_or(&page.output);
+            let title = page.title.as_deref().unwrap_or_default();
+            (relative.to_path_buf(), title)
+        })
+        .collect::<Vec<(PathBuf, &str)>>();
+    pages.sort_by_key(|(path, _)| {
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        (dir, !is_index_file(path), path.clone())
+    });
+
+    let mut nav = String::from("nav:\n");
+    let mut open_dirs: Vec<String> = vec![];
+    for (path, title) in pages {
+        let dirs = path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        let common = open_dirs
+            .iter()
+            .zip(&dirs)
+            .take_while(|(a, b)| a == b)
+            .count();
+        open_dirs.truncate(common);
+        for dir in &dirs[common..] {
+            nav.push_str(&format!(
+                "{}- {}:\n",
+                "  ".repeat(open_dirs.len() + 1),
+                serde_json::Value::from(dir.as_str())
+            ));
+            open_dirs.push(dir.clone());
+        }
+        nav.push_str(&format!(
+            "{}- {}: {}\n",
+            "  ".repeat(open_dirs.len() + 1),
+            serde_json::Value::from(title),
+            serde_json::Value::from(path.to_string_lossy().replace('\\', "/"))
+        ));
+    }
+
+    let nav_file = output.join("mkdocs-nav.yml");
+    fs::write(&nav_file, nav).unwrap_or_else(|_| panic!("Cannot write file: {:?}", nav_file));
+}
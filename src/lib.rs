@@ -0,0 +1,55 @@
+//! The library behind the `htmd` command line tool.
+//!
+//! It reads the same options files as the cli, and converts batches of files the same
+//! way `htmd --output <dir>` does.
+//!
+//! ```no_run
+//! use htmd_cli::{BatchConverter, Config};
+//!
+//! let config = Config::from_toml_file("htmd-options.toml").unwrap();
+//! let files = vec!["pages/index.html".into(), "pages/guide/setup.html".into()];
+//! let batch = BatchConverter::new(config);
+//! for entry in batch.plan(&files, "converted".as_ref()) {
+//!     println!("{:?} -> {:?}", entry.input, entry.output);
+//! }
+//! let result = batch.convert(&files, "converted".as_ref()).unwrap();
+//! assert!(result.failures.is_empty());
+//! ```
+
+mod batch;
+mod cache_util;
+mod check_util;
+pub mod cli;
+mod cli_options;
+mod code_util;
+mod completion_util;
+mod config;
+mod config_util;
+mod dom_util;
+mod flavor_util;
+mod index_util;
+mod io_util;
+mod layout_util;
+mod limit_util;
+mod math_util;
+mod mcp_util;
+mod merge_util;
+mod meta_util;
+mod option_util;
+mod path_util;
+mod pool_util;
+mod progress_util;
+mod server_util;
+mod table_util;
+mod worker_util;
+
+pub use batch::{BatchConverter, BatchResult, FileEvent, PlanEntry};
+pub use code_util::CodeOptions;
+pub use config::Config;
+pub use flavor_util::Flavor;
+pub use index_util::Page;
+pub use io_util::InputLimit;
+pub use layout_util::Layout;
+pub use limit_util::{ConvertLimits, Failure};
+pub use math_util::MathStyle;
+pub use table_util::{TableFallback, TableOptions, TableSpan};
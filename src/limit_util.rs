@@ -0,0 +1,189 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    process::exit,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use clap::ArgMatches;
+use htmd::HtmlToMarkdown;
+
+use crate::dom_util::{parse_html, walk_elements};
+
+/// Safety limits of converting a single document.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ConvertLimits {
+    pub timeout: Option<Duration>,
+    /// Maximum nesting depth of elements.
+    pub max_depth: Option<usize>,
+    /// Maximum number of elements.
+    pub max_nodes: Option<usize>,
+}
+
+/// A file that was not converted, reported in the run summary.
+#[derive(Debug)]
+pub struct Failure {
+    pub file: PathBuf,
+    pub reason: String,
+}
+
+/// Print the failed files to stderr and exit with 1, does nothing without failures.
+pub(crate) fn exit_on_failures(failures: &[Failure]) {
+    if failures.is_empty() {
+        return;
+    }
+    eprintln!("Failed to convert {} file(s):", failures.len());
+    for Failure { file, reason } in failures {
+        eprintln!("  {:?}: {}", file, reason);
+    }
+    exit(1);
+}
+
+pub(crate) fn resolve_convert_limits(matches: &ArgMatches) -> ConvertLimits {
+    ConvertLimits {
+        timeout: matches.get_one::<Duration>("timeout-per-file").copied(),
+        max_depth: matches.get_one::<usize>("max-depth").copied(),
+        max_nodes: matches.get_one::<usize>("max-nodes").copied(),
+    }
+}
+
+/// Parse durations like `10s`, `500ms`, `2m`, a plain number is in seconds.
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let invalid = || {
+        format!(
+            "invalid duration '{}', expected e.g. 10s, 500ms or 2m",
+            value
+        )
+    };
+    let (number, unit) = match value.find(|ch: char| !ch.is_ascii_digit() && ch != '.') {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number = number.parse::<f64>().map_err(|_| invalid())?;
+    let secs = match unit.trim() {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(invalid)
+}
+
+/// Convert `html` within the limits.
+///
+/// With a timeout, every conversion runs on a thread of its own. A thread that times out
+/// cannot be stopped, it keeps running in the background until it finishes or the process
+/// exits, and its result is dropped.
+pub(crate) fn convert_with_limits(
+    converter: &Arc<HtmlToMarkdown>,
+    html: String,
+    limits: ConvertLimits,
+) -> Result<String, String> {
+    let Some(timeout) = limits.timeout else {
+        return convert_checked(converter, &html, limits);
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let converter = Arc::clone(converter);
+    thread::spawn(move || {
+        let _ = sender.send(convert_checked(&converter, &html, limits));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(format!("Timed out after {:?}", timeout)),
+        Err(RecvTimeoutError::Disconnected) => Err("Conversion thread exited".to_string()),
+    }
+}
+
+/// Check and convert `html`, a panic fails only this document.
+fn convert_checked(
+    converter: &HtmlToMarkdown,
+    html: &str,
+    limits: ConvertLimits,
+) -> Result<String, String> {
+    let convert = || {
+        check_document(html, limits)?;
+        converter
+            .convert(html)
+            .map_err(|e| format!("Failed to parse html: {}", e))
+    };
+    panic::catch_unwind(AssertUnwindSafe(convert)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(format!("Conversion panicked: {}", message))
+    })
+}
+
+/// Check the element depth and count, the document is only parsed if a limit is set.
+fn check_document(html: &str, limits: ConvertLimits) -> Result<(), String> {
+    if limits.max_depth.is_none() && limits.max_nodes.is_none() {
+        return Ok(());
+    }
+    let dom = parse_html(html)?;
+
+    let mut nodes = 0;
+    walk_elements(&dom, |_, depth| {
+        nodes += 1;
+        if limits.max_nodes.is_some_and(|max_nodes| nodes > max_nodes) {
+            return Err(format!(
+                "More than {} elements (--max-nodes)",
+                limits.max_nodes.unwrap()
+            ));
+        }
+        if limits.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return Err(format!(
+                "Elements are nested deeper than {} (--max-depth)",
+                limits.max_depth.unwrap()
+            ));
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use htmd::{
+        element_handler::{HandlerResult, Handlers},
+        Element, HtmlToMarkdown,
+    };
+
+    use super::{convert_with_limits, ConvertLimits};
+
+    fn blink_handler(_: &dyn Handlers, _: Element) -> Option<HandlerResult> {
+        panic!("blinking")
+    }
+
+    #[test]
+    fn panics_fail_the_document() {
+        let converter = Arc::new(
+            HtmlToMarkdown::builder()
+                .add_handler(vec!["blink"], blink_handler)
+                .build(),
+        );
+        let timeout = ConvertLimits {
+            timeout: Some(Duration::from_secs(10)),
+            ..ConvertLimits::default()
+        };
+        for limits in [ConvertLimits::default(), timeout] {
+            let result = convert_with_limits(&converter, "<blink>Hi</blink>".to_string(), limits);
+            assert_eq!(result, Err("Conversion panicked: blinking".to_string()));
+            let result = convert_with_limits(&converter, "<p>Hi</p>".to_string(), limits);
+            assert_eq!(result, Ok("Hi".to_string()));
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use std::rc::Rc;
+
+use htmd::{
+    element_handler::{HandlerResult, Handlers},
+    Element, HtmlToMarkdownBuilder,
+};
+use markup5ever_rcdom::{Node, NodeData};
+
+use crate::flavor_util::{node_attr, parent_node, tag_name};
+
+/// How math is written, from MathJax scripts, KaTeX, and MathML.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MathStyle {
+    /// Math is not recovered, scripts and MathML are converted like other elements.
+    None,
+    /// `$x$` and `$$x$$`.
+    Dollars,
+    /// `\(x\)` and `\[x\]`.
+    Brackets,
+    /// `` $`x`$ `` and ` ```math ` blocks, like GitLab and GitHub.
+    Code,
+}
+
+/// Elements of the MathJax 2 output, the TeX is in the script next to them.
+const MATHJAX_OUTPUT_CLASSES: [&str; 7] = [
+    "MathJax_Preview",
+    "MathJax",
+    "MathJax_Display",
+    "MathJax_SVG",
+    "MathJax_SVG_Display",
+    "MathJax_CHTML",
+    "MathJax_MathML",
+];
+
+pub(crate) fn apply_math(
+    builder: HtmlToMarkdownBuilder,
+    style: MathStyle,
+) -> HtmlToMarkdownBuilder {
+    if style == MathStyle::None {
+        return builder;
+    }
+    builder
+        .add_handler(
+            vec!["script"],
+            move |handlers: &dyn Handlers, element: Element| {
+                math_script_handler(handlers, element, style)
+            },
+        )
+        .add_handler(
+            vec!["math"],
+            move |handlers: &dyn Handlers, element: Element| math_handler(handlers, element, style),
+        )
+        .add_handler(
+            vec!["span", "div"],
+            move |handlers: &dyn Handlers, element: Element| {
+                katex_handler(handlers, element, style)
+            },
+        )
+        .add_handler(
+            vec!["mjx-container"],
+            move |handlers: &dyn Handlers, element: Element| {
+                mathjax_container_handler(handlers, element, style)
+            },
+        )
+        .add_handler(vec!["noscript"], math_noscript_handler)
+}
+
+/// `<script type="math/tex">` of MathJax 2, `; mode=display` for display math.
+fn math_script_handler(
+    handlers: &dyn Handlers,
+    element: Element,
+    style: MathStyle,
+) -> Option<HandlerResult> {
+    let Some(display) = math_script_display(element.node) else {
+        return handlers.fallback(element);
+    };
+    let math = math_markdown(&text_content(element.node), display, style);
+    if display {
+        return Some(math.into());
+    }
+    // htmd trims the spaces around scripts like around blocks, inline math keeps them
+    let space_before =
+        adjacent_text(element.node, true).is_some_and(|text| text.ends_with(char::is_whitespace));
+    let space_after = adjacent_text(element.node, false)
+        .is_some_and(|text| text.starts_with(char::is_whitespace));
+    Some(
+        format!(
+            "{}{}{}",
+            if space_before { " " } else { "" },
+            math,
+            if space_after { " " } else { "" }
+        )
+        .into(),
+    )
+}
+
+/// Whether a math script is display math, `None` for other scripts.
+fn math_script_display(node: &Rc<Node>) -> Option<bool> {
+    if tag_name(node) != Some("script") {
+        return None;
+    }
+    let kind = node_attr(node, "type")?.to_ascii_lowercase();
+    let mut parts = kind.split(';').map(|part| part.trim());
+    if parts.next() != Some("math/tex") {
+        return None;
+    }
+    Some(parts.any(|part| part.replace(' ', "") == "mode=display"))
+}
+
+/// The `<noscript>` fallback of a math script is dropped, the script has the math already.
+fn math_noscript_handler(handlers: &dyn Handlers, element: Element) -> Option<HandlerResult> {
+    let follows_math_script = previous_element(element.node)
+        .is_some_and(|previous| math_script_display(&previous).is_some());
+    if follows_math_script {
+        Some("".into())
+    } else {
+        handlers.fallback(element)
+    }
+}
+
+fn math_handler(
+    _handlers: &dyn Handlers,
+    element: Element,
+    style: MathStyle,
+) -> Option<HandlerResult> {
+    let display = node_attr(element.node, "display").as_deref() == Some("block");
+    Some(math_markdown(&math_tex(element.node), display, style).into())
+}
+
+/// KaTeX keeps the TeX in an annotation of its MathML, the HTML output is dropped.
+fn katex_handler(
+    handlers: &dyn Handlers,
+    element: Element,
+    style: MathStyle,
+) -> Option<HandlerResult> {
+    if is_mathjax_output(element.node) {
+        return Some("".into());
+    }
+    let class = node_attr(element.node, "class").unwrap_or_default();
+    let classes = class.split_whitespace().collect::<Vec<&str>>();
+    let display = classes.contains(&"katex-display");
+    if !display && !classes.contains(&"katex") {
+        return handlers.fallback(element);
+    }
+    let Some(math) = find_element(element.node, "math") else {
+        return handlers.fallback(element);
+    };
+    let display = display || node_attr(&math, "display").as_deref() == Some("block");
+    Some(math_markdown(&math_tex(&math), display, style).into())
+}
+
+/// MathJax 3 keeps MathML for assistive technology in its output.
+fn mathjax_container_handler(
+    handlers: &dyn Handlers,
+    element: Element,
+    style: MathStyle,
+) -> Option<HandlerResult> {
+    let Some(math) = find_element(element.node, "math") else {
+        return handlers.fallback(element);
+    };
+    let display = node_attr(element.node, "display").as_deref() == Some("true")
+        || node_attr(&math, "display").as_deref() == Some("block");
+    Some(math_markdown(&math_tex(&math), display, style).into())
+}
+
+fn math_markdown(tex: &str, display: bool, style: MathStyle) -> String {
+    let tex = tex.trim();
+    if tex.is_empty() {
+        return String::new();
+    }
+    if display {
+        let block = match style {
+            MathStyle::Dollars => format!("$$\n{}\n$$", tex),
+            MathStyle::Brackets => format!("\\[\n{}\n\\]", tex),
+            MathStyle::Code => {
+                let fence = if tex.contains("```") { "````" } else { "```" };
+                format!("{}math\n{}\n{}", fence, tex, fence)
+            }
+            MathStyle::None => unreachable!("Math handlers are only added with a math style"),
+        };
+        return format!("\n\n{}\n\n", block);
+    }
+    // Inline math is on one line
+    let tex = tex.split_whitespace().collect::<Vec<&str>>().join(" ");
+    match style {
+        MathStyle::Dollars => format!("${}$", tex),
+        MathStyle::Brackets => format!("\\({}\\)", tex),
+        MathStyle::Code => format!("$`{}`$", tex),
+        MathStyle::None => unreachable!("Math handlers are only added with a math style"),
+    }
+}
+
+/// The TeX of a `<math>` element, from its TeX annotation or converted from the MathML.
+fn math_tex(math: &Rc<Node>) -> String {
+    let annotation = find_elements(math, "annotation")
+        .into_iter()
+        .find(|annotation| {
+            node_attr(annotation, "encoding").is_some_and(|encoding| {
+                let encoding = encoding.to_ascii_lowercase();
+                encoding == "application/x-tex" || encoding == "tex"
+            })
+        });
+    match annotation {
+        Some(annotation) => text_content(&annotation),
+        None => mathml_to_tex(math),
+    }
+}
+
+/// Convert basic MathML to TeX: tokens, scripts, fractions, roots, and tables.
+fn mathml_to_tex(node: &Rc<Node>) -> String {
+    let children = node
+        .children
+        .borrow()
+        .iter()
+        .filter(|child| matches!(child.data, NodeData::Element { .. }))
+        .cloned()
+        .collect::<Vec<Rc<Node>>>();
+    let child = |index: usize| children.get(index).map(mathml_to_tex).unwrap_or_default();
+    let text = || text_content(node).trim().to_string();
+
+    match tag_name(node).unwrap_or_default() {
+        "mi" => identifier_tex(&text()),
+        "mn" => escape_tex(&text()),
+        "mo" => operator_tex(&text()),
+        "mtext" | "ms" => format!("\\text{{{}}}", escape_tex(&text())),
+        "mspace" => "\\ ".to_string(),
+        "msup" => format!("{}^{{{}}}", base_tex(&child(0)), child(1)),
+        "msub" => format!("{}_{{{}}}", base_tex(&child(0)), child(1)),
+        "msubsup" => format!("{}_{{{}}}^{{{}}}", base_tex(&child(0)), child(1), child(2)),
+        "mfrac" => format!("\\frac{{{}}}{{{}}}", child(0), child(1)),
+        "msqrt" => format!("\\sqrt{{{}}}", join_tex(children.iter().map(mathml_to_tex))),
+        "mroot" => format!("\\sqrt[{}]{{{}}}", child(1), child(0)),
+        "mover" => match accent_tex(&child(1)) {
+            Some(accent) => format!("{}{{{}}}", accent, child(0)),
+            None if is_big_operator(&child(0)) => format!("{}^{{{}}}", child(0), child(1)),
+            None => format!("\\overset{{{}}}{{{}}}", child(1), child(0)),
+        },
+        "munder" if is_big_operator(&child(0)) => format!("{}_{{{}}}", child(0), child(1)),
+        "munder" => format!("\\underset{{{}}}{{{}}}", child(1), child(0)),
+        "munderover" => format!("{}_{{{}}}^{{{}}}", child(0), child(1), child(2)),
+        "mfenced" => {
+            let open = node_attr(node, "open").unwrap_or_else(|| "(".to_string());
+            let close = node_attr(node, "close").unwrap_or_else(|| ")".to_string());
+            let items = children.iter().map(mathml_to_tex).collect::<Vec<String>>();
+            format!(
+                "{}{}{}",
+                operator_tex(&open),
+                items.join(", "),
+                operator_tex(&close)
+            )
+        }
+        "mtable" => {
+            let rows = children.iter().map(mathml_to_tex).collect::<Vec<String>>();
+            format!("\\begin{{matrix}} {} \\end{{matrix}}", rows.join(" \\\\ "))
+        }
+        "mtr" | "mlabeledtr" => children
+            .iter()
+            .map(mathml_to_tex)
+            .collect::<Vec<String>>()
+            .join(" & "),
+        "semantics" => child(0),
+        "annotation" | "annotation-xml" | "mphantom" | "none" | "mprescripts" => String::new(),
+        _ => join_tex(children.iter().map(mathml_to_tex)),
+    }
+}
+
+/// Join TeX, with a space between a command and a following letter.
+fn join_tex(parts: impl Iterator<Item = String>) -> String {
+    let mut tex = String::new();
+    for part in parts {
+        let command_end = tex.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let ends_with_command = command_end.len() < tex.len() && command_end.ends_with('\\');
+        if ends_with_command && part.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            tex.push(' ');
+        }
+        tex.push_str(&part);
+    }
+    tex
+}
+
+/// Bases of scripts in braces if they are more than one token, like `{(a+b)}^{2}`.
+fn base_tex(tex: &str) -> String {
+    let is_command = tex.starts_with('\\') && tex[1..].chars().all(|c| c.is_ascii_alphabetic());
+    if tex.chars().count() <= 1 || is_command {
+        tex.to_string()
+    } else {
+        format!("{{{}}}", tex)
+    }
+}
+
+fn is_big_operator(tex: &str) -> bool {
+    matches!(
+        tex,
+        "\\sum"
+            | "\\prod"
+            | "\\int"
+            | "\\oint"
+            | "\\lim"
+            | "\\max"
+            | "\\min"
+            | "\\bigcup"
+            | "\\bigcap"
+    )
+}
+
+fn accent_tex(tex: &str) -> Option<&'static str> {
+    match tex {
+        "^" | "\\hat{}" => Some("\\hat"),
+        "¯" | "‾" | "―" | "_" | "\\_" => Some("\\overline"),
+        "\\to" | "\\rightarrow" => Some("\\vec"),
+        "~" | "\\sim" => Some("\\tilde"),
+        "˙" | "." => Some("\\dot"),
+        _ => None,
+    }
+}
+
+/// Function names of `mi`, written as TeX commands.
+const FUNCTIONS: [&str; 26] = [
+    "sin", "cos", "tan", "cot", "sec", "csc", "sinh", "cosh", "tanh", "arcsin", "arccos", "arctan",
+    "log", "ln", "exp", "lim", "max", "min", "sup", "inf", "det", "gcd", "deg", "dim", "ker",
+    "arg",
+];
+
+fn identifier_tex(text: &str) -> String {
+    if let Some(command) = symbol_tex(text) {
+        return command.to_string();
+    }
+    if FUNCTIONS.contains(&text) {
+        return format!("\\{}", text);
+    }
+    if text.chars().count() > 1 {
+        return format!("\\mathrm{{{}}}", escape_tex(text));
+    }
+    escape_tex(text)
+}
+
+fn operator_tex(text: &str) -> String {
+    match text {
+        // Invisible function application and times
+        "\u{2061}" | "\u{2062}" | "\u{2063}" | "" => String::new(),
+        "−" => "-".to_string(),
+        "{" => "\\{".to_string(),
+        "}" => "\\}".to_string(),
+        _ => symbol_tex(text)
+            .map(|command| command.to_string())
+            .or_else(|| FUNCTIONS.contains(&text).then(|| format!("\\{}", text)))
+            .unwrap_or_else(|| escape_tex(text)),
+    }
+}
+
+/// TeX commands of Greek letters and common operators.
+fn symbol_tex(text: &str) -> Option<&'static str> {
+    let command = match text {
+        "α" => "\\alpha",
+        "β" => "\\beta",
+        "γ" => "\\gamma",
+        "δ" => "\\delta",
+        "ε" | "ϵ" => "\\epsilon",
+        "ζ" => "\\zeta",
+        "η" => "\\eta",
+        "θ" => "\\theta",
+        "ι" => "\\iota",
+        "κ" => "\\kappa",
+        "λ" => "\\lambda",
+        "μ" => "\\mu",
+        "ν" => "\\nu",
+        "ξ" => "\\xi",
+        "π" => "\\pi",
+        "ρ" => "\\rho",
+        "σ" => "\\sigma",
+        "τ" => "\\tau",
+        "υ" => "\\upsilon",
+        "φ" | "ϕ" => "\\phi",
+        "χ" => "\\chi",
+        "ψ" => "\\psi",
+        "ω" => "\\omega",
+        "Γ" => "\\Gamma",
+        "Δ" => "\\Delta",
+        "Θ" => "\\Theta",
+        "Λ" => "\\Lambda",
+        "Ξ" => "\\Xi",
+        "Π" => "\\Pi",
+        "Σ" => "\\Sigma",
+        "Φ" => "\\Phi",
+        "Ψ" => "\\Psi",
+        "Ω" => "\\Omega",
+        "∞" => "\\infty",
+        "∂" => "\\partial",
+        "∇" => "\\nabla",
+        "×" => "\\times",
+        "÷" => "\\div",
+        "±" => "\\pm",
+        "∓" => "\\mp",
+        "·" | "⋅" => "\\cdot",
+        "≤" => "\\leq",
+        "≥" => "\\geq",
+        "≠" => "\\neq",
+        "≈" => "\\approx",
+        "≡" => "\\equiv",
+        "∼" => "\\sim",
+        "∝" => "\\propto",
+        "∈" => "\\in",
+        "∉" => "\\notin",
+        "⊂" => "\\subset",
+        "⊆" => "\\subseteq",
+        "∪" => "\\cup",
+        "∩" => "\\cap",
+        "∅" => "\\emptyset",
+        "∀" => "\\forall",
+        "∃" => "\\exists",
+        "¬" => "\\neg",
+        "∧" => "\\wedge",
+        "∨" => "\\vee",
+        "→" => "\\to",
+        "←" => "\\leftarrow",
+        "↔" => "\\leftrightarrow",
+        "⇒" => "\\Rightarrow",
+        "⇐" => "\\Leftarrow",
+        "⇔" => "\\Leftrightarrow",
+        "↦" => "\\mapsto",
+        "∑" => "\\sum",
+        "∏" => "\\prod",
+        "∫" => "\\int",
+        "∮" => "\\oint",
+        "⋃" => "\\bigcup",
+        "⋂" => "\\bigcap",
+        "…" => "\\ldots",
+        "⋯" => "\\cdots",
+        "⋮" => "\\vdots",
+        "′" => "'",
+        "″" => "''",
+        "⟨" => "\\langle",
+        "⟩" => "\\rangle",
+        "|" | "∣" => "|",
+        "‖" | "∥" => "\\|",
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn escape_tex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str("\\backslash "),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn text_content(node: &Rc<Node>) -> String {
+    let mut text = String::new();
+    for child in node.children.borrow().iter() {
+        match &child.data {
+            NodeData::Text { contents } => text.push_str(&contents.borrow()),
+            NodeData::Element { .. } => text.push_str(&text_content(child)),
+            _ => {}
+        }
+    }
+    text
+}
+
+fn find_element(node: &Rc<Node>, tag: &str) -> Option<Rc<Node>> {
+    find_elements(node, tag).into_iter().next()
+}
+
+fn find_elements(node: &Rc<Node>, tag: &str) -> Vec<Rc<Node>> {
+    let mut found = vec![];
+    for child in node.children.borrow().iter() {
+        if tag_name(child) == Some(tag) {
+            found.push(Rc::clone(child));
+        }
+        found.extend(find_elements(child, tag));
+    }
+    found
+}
+
+fn is_mathjax_output(node: &Rc<Node>) -> bool {
+    node_attr(node, "class").is_some_and(|class| {
+        class
+            .split_whitespace()
+            .any(|class| MATHJAX_OUTPUT_CLASSES.contains(&class))
+    })
+}
+
+/// The text next to a node, skipping the MathJax output and `<noscript>` fallbacks that
+/// are dropped. `None` if an element is next to it.
+fn adjacent_text(node: &Rc<Node>, before: bool) -> Option<String> {
+    let parent = parent_node(node)?;
+    let children = parent.children.borrow();
+    let index = children.iter().position(|child| Rc::ptr_eq(child, node))?;
+    let mut siblings: Box<dyn Iterator<Item = &Rc<Node>>> = if before {
+        Box::new(children[..index].iter().rev())
+    } else {
+        Box::new(children[index + 1..].iter())
+    };
+    siblings.find_map(|sibling| match &sibling.data {
+        NodeData::Text { contents } => Some(Some(contents.borrow().to_string())),
+        NodeData::Element { .. }
+            if is_mathjax_output(sibling) || tag_name(sibling) == Some("noscript") =>
+        {
+            None
+        }
+        NodeData::Element { .. } => Some(None),
+        _ => None,
+    })?
+}
+
+/// The element before a node, skipping whitespace.
+fn previous_element(node: &Rc<Node>) -> Option<Rc<Node>> {
+    let parent = parent_node(node)?;
+    let children = parent.children.borrow();
+    let index = children.iter().position(|child| Rc::ptr_eq(child, node))?;
+    children[..index]
+        .iter()
+        .rev()
+        .find(|child| match &child.data {
+            NodeData::Text { contents } => !contents.borrow().trim().is_empty(),
+            _ => true,
+        })
+        .filter(|child| matches!(child.data, NodeData::Element { .. }))
+        .cloned()
+}
@@ -0,0 +1,306 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint};
+use serde_json::{json, Value};
+
+use crate::{
+    config_util::read_cli_options_from_json,
+    io_util::{read_html_file, InputLimit},
+    option_util::options_schema,
+};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Stop listing a directory after this many files, the result goes into one response.
+const MAX_DIRECTORY_FILES: usize = 500;
+
+pub(crate) fn mcp_command() -> Command {
+    Command::new("mcp")
+        .about("Serve conversion tools over the Model Context Protocol on stdio")
+        .arg(
+            Arg::new("root")
+                .long("root")
+                .help(
+                    "Directory that tools can read files from, can be repeated;\n\
+                    defaults to the current dir",
+                )
+                .value_hint(ValueHint::DirPath)
+                .num_args(1)
+                .action(ArgAction::Append),
+        )
+}
+
+/// Serve JSON-RPC messages from stdin until it is closed.
+pub(crate) fn run_mcp_server(matches: &ArgMatches) {
+    let roots = resolve_roots(matches);
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("Cannot read text from stdin.");
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle_message(&message, &roots),
+            Err(e) => Some(error_response(
+                Value::Null,
+                -32700,
+                &format!("Parse error: {}", e),
+            )),
+        };
+        // Notifications have no response
+        let Some(response) = response else {
+            continue;
+        };
+        writeln!(out, "{}", response)
+            .and_then(|_| out.flush())
+            .expect("Failed to write to stdout");
+    }
+}
+
+fn resolve_roots(matches: &ArgMatches) -> Vec<PathBuf> {
+    let roots = match matches.get_many::<String>("root") {
+        Some(roots) => roots.map(PathBuf::from).collect(),
+        None => vec![PathBuf::from(".")],
+    };
+    roots
+        .iter()
+        .map(|root| {
+            fs::canonicalize(root).unwrap_or_else(|_| {
+                eprintln!("Root directory does not exist: {:?}", root);
+                exit(1);
+            })
+        })
+        .collect()
+}
+
+fn handle_message(message: &Value, roots: &[PathBuf]) -> Option<Value> {
+    let id = message.get("id")?.clone();
+    let params = message.get("params").cloned().unwrap_or_default();
+    let result = match message.get("method").and_then(|method| method.as_str()) {
+        Some("initialize") => Ok(json!({
+            "protocolVersion": params
+                .get("protocolVersion")
+                .and_then(|version| version.as_str())
+                .unwrap_or(PROTOCOL_VERSION),
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "htmd", "version": env!("CARGO_PKG_VERSION") },
+        })),
+        Some("ping") => Ok(json!({})),
+        Some("tools/list") => Ok(json!({ "tools": tool_definitions() })),
+        Some("tools/call") => call_tool(&params, roots),
+        Some(method) => Err((-32601, format!("Method not found: {}", method))),
+        None => Err((-32600, "Invalid request".to_string())),
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error_response(id, code, &message),
+    })
+}
+
+fn tool_definitions() -> Value {
+    let options = options_schema();
+    json!([
+        {
+            "name": "convert_html",
+            "description": "Convert an HTML string to Markdown",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "html": { "type": "string", "description": "The HTML to convert" },
+                    "options": options,
+                },
+                "required": ["html"],
+            },
+        },
+        {
+            "name": "convert_file",
+            "description": "Convert an HTML file to Markdown",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Path of the HTML file" },
+                    "options": options,
+                },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "convert_directory",
+            "description": "Convert the HTML files in a directory to Markdown, one text item per file",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Path of the directory" },
+                    "recursive": {
+                        "type": "boolean",
+                        "description": "Include sub directories, defaults to true",
+                    },
+                    "options": options,
+                },
+                "required": ["path"],
+            },
+        },
+    ])
+}
+
+/// Tool errors are returned as results with `isError`, so the model can see them.
+fn call_tool(params: &Value, roots: &[PathBuf]) -> Result<Value, (i64, String)> {
+    let name = params
+        .get("name")
+        .and_then(|name| name.as_str())
+        .ok_or_else(|| (-32602, "Missing tool name".to_string()))?;
+    let arguments = params.get("arguments").cloned().unwrap_or_default();
+
+    let texts = match name {
+        "convert_html" => convert_html_tool(&arguments),
+        "convert_file" => convert_file_tool(&arguments, roots),
+        "convert_directory" => convert_directory_tool(&arguments, roots),
+        _ => return Err((-32602, format!("Unknown tool: {}", name))),
+    };
+    Ok(match texts {
+        Ok(texts) => json!({
+            "content": texts
+                .into_iter()
+                .map(|text| json!({ "type": "text", "text": text }))
+                .collect::<Vec<Value>>(),
+            "isError": false,
+        }),
+        Err(message) => json!({
+            "content": [{ "type": "text", "text": message }],
+            "isError": true,
+        }),
+    })
+}
+
+fn convert_html_tool(arguments: &Value) -> Result<Vec<String>, String> {
+    let html = string_argument(arguments, "html")?;
+    Ok(vec![
+        convert(arguments, vec![(None, html.to_string())])?.remove(0)
+    ])
+}
+
+fn convert_file_tool(arguments: &Value, roots: &[PathBuf]) -> Result<Vec<String>, String> {
+    let file = resolve_path(string_argument(arguments, "path")?, roots)?;
+    if !file.is_file() {
+        return Err(format!("Not a file: {:?}", file));
+    }
+    let html = read_html(&file)?;
+    Ok(vec![convert(arguments, vec![(None, html)])?.remove(0)])
+}
+
+fn convert_directory_tool(arguments: &Value, roots: &[PathBuf]) -> Result<Vec<String>, String> {
+    let dir = resolve_path(string_argument(arguments, "path")?, roots)?;
+    if !dir.is_dir() {
+        return Err(format!("Not a directory: {:?}", dir));
+    }
+    let recursive = arguments
+        .get("recursive")
+        .and_then(|recursive| recursive.as_bool())
+        .unwrap_or(true);
+
+    let mut files = vec![];
+    collect_html_files(&dir, recursive, &mut files)?;
+    files.sort();
+    if files.is_empty() {
+        return Err(format!("No HTML files in {:?}", dir));
+    }
+
+    let docs = files
+        .iter()
+        .map(|file| {
+            let relative = file.strip_prefix(&dir).unwrap_or(file);
+            Ok((
+                Some(relative.to_string_lossy().to_string()),
+                read_html(file)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    convert(arguments, docs)
+}
+
+/// Convert documents with the `options` argument, named documents get a path comment.
+fn convert(arguments: &Value, docs: Vec<(Option<String>, String)>) -> Result<Vec<String>, String> {
+    let options = read_cli_options_from_json(arguments.get("options").unwrap_or_default())
+        .map_err(|e| e.to_string())?;
+    let converter = options.into_converter();
+    docs.into_iter()
+        .map(|(path, html)| {
+            let md = converter
+                .convert(&html)
+                .map_err(|e| format!("Failed to convert: {}", e))?;
+            Ok(match path {
+                Some(path) => format!("<!-- {} -->\n\n{}", path, md),
+                None => md,
+            })
+        })
+        .collect()
+}
+
+/// Resolve a path within the roots, relative paths are relative to the first root.
+fn resolve_path(path: &str, roots: &[PathBuf]) -> Result<PathBuf, String> {
+    let path = roots[0].join(path);
+    let path = fs::canonicalize(&path).map_err(|_| format!("Path does not exist: {:?}", path))?;
+    if roots.iter().any(|root| path.starts_with(root)) {
+        Ok(path)
+    } else {
+        Err(format!("Path is outside of the allowed roots: {:?}", path))
+    }
+}
+
+fn collect_html_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Cannot read dir {:?}: {}", dir, e))?;
+    for entry in entries.flatten() {
+        // Symlinks are skipped, they could point outside of the roots
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_dir() && recursive {
+            collect_html_files(&path, recursive, files)?;
+        } else if file_type.is_file() && is_html_file(&path) {
+            if files.len() == MAX_DIRECTORY_FILES {
+                return Err(format!(
+                    "More than {} HTML files in {:?}",
+                    MAX_DIRECTORY_FILES, dir
+                ));
+            }
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_html_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"))
+}
+
+fn read_html(file: &Path) -> Result<String, String> {
+    let limit = InputLimit {
+        max_size: None,
+        truncate: false,
+    };
+    read_html_file(file, limit).map_err(|e| e.to_string())
+}
+
+fn string_argument<'a>(arguments: &'a Value, name: &str) -> Result<&'a str, String> {
+    arguments
+        .get(name)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| format!("\"{}\" must be a string", name))
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
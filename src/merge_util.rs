@@ -0,0 +1,302 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use clap::ArgMatches;
+
+use crate::path_util::normalize_path;
+
+pub(crate) enum MergeOrder {
+    /// Sort by path.
+    Sorted,
+    /// Sort by path, comparing digit runs by their numeric value.
+    Natural,
+    /// Follow the entries of a list file, unlisted files are appended in sorted order.
+    Toc(PathBuf),
+}
+
+pub(crate) fn resolve_merge_order(matches: &ArgMatches) -> MergeOrder {
+    if let Some(toc) = matches.get_one::<String>("merge-toc") {
+        return MergeOrder::Toc(PathBuf::from(toc));
+    }
+    match matches.get_one::<String>("merge-order").unwrap().as_str() {
+        "natural" => MergeOrder::Natural,
+        _ => MergeOrder::Sorted,
+    }
+}
+
+pub(crate) fn order_files(files: &[PathBuf], order: &MergeOrder) -> Vec<PathBuf> {
+    let mut files = files.to_vec();
+    files.sort();
+    match order {
+        MergeOrder::Sorted => files,
+        MergeOrder::Natural => {
+            files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+            files
+        }
+        MergeOrder::Toc(toc) => order_files_by_toc(files, toc),
+    }
+}
+
+/// Merge converted documents into one, `files` and `docs` must be in the same order.
+pub(crate) fn merge_documents(files: &[PathBuf], docs: &[String], base_dir: &Path) -> String {
+    let relative_paths = files
+        .iter()
+        .map(|file| normalize_path(file.strip_prefix(base_dir).unwrap_or(file)))
+        .collect::<Vec<PathBuf>>();
+
+    let anchors = relative_paths
+        .iter()
+        .map(|path| (path.clone(), anchor_of(path)))
+        .collect::<HashMap<PathBuf, String>>();
+
+    let mut merged = String::new();
+    for (path, md) in relative_paths.iter().zip(docs) {
+        let depth = path.components().count().saturating_sub(1);
+        let md = demote_headings(md, depth);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        // Sections keep their fragment, it is the anchor of the heading when
+        // the page used the usual heading ids
+        let md = rewrite_links(&md, dir, |target, fragment| {
+            let anchor = anchors.get(target)?;
+            if fragment.is_empty() {
+                Some(format!("#{}", anchor))
+            } else {
+                Some(format!("#{}", fragment))
+            }
+        });
+        if !merged.is_empty() {
+            merged.push_str("\n\n");
+        }
+        merged.push_str(&format!("<a id=\"{}\"></a>\n\n", anchors[path]));
+        merged.push_str(&md);
+    }
+    merged
+}
+
+fn order_files_by_toc(files: Vec<PathBuf>, toc: &Path) -> Vec<PathBuf> {
+    let Ok(text) = fs::read_to_string(toc) else {
+        eprintln!("Cannot read merge toc file: {:?}", toc);
+        exit(1);
+    };
+    let toc_dir = toc.parent().unwrap_or(Path::new(""));
+
+    let mut remaining = files;
+    let mut ordered: Vec<PathBuf> = vec![];
+    for entry in text.lines().filter_map(parse_toc_entry) {
+        let entry = normalize_path(&toc_dir.join(entry));
+        if let Some(index) = remaining
+            .iter()
+            .position(|file| normalize_path(file) == entry)
+        {
+            ordered.push(remaining.remove(index));
+        } else {
+            eprintln!("Merge toc entry is not an input file: {:?}", entry);
+        }
+    }
+    ordered.extend(remaining);
+    ordered
+}
+
+/// Accepts plain paths and Markdown list items like `- [Title](path.html)`.
+fn parse_toc_entry(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    if let Some(start) = line.find("](") {
+        let target = &line[start + 2..];
+        let end = target.find(')')?;
+        return Some(&target[..end]);
+    }
+    Some(line.trim_start_matches(['-', '*', ' ']))
+}
+
+fn anchor_of(path: &Path) -> String {
+    let path = path.with_extension("");
+    let mut anchor = String::new();
+    for ch in path.to_string_lossy().chars() {
+        if ch.is_alphanumeric() || ch == '-' || ch == '_' {
+            anchor.extend(ch.to_lowercase());
+        } else if !anchor.ends_with('-') {
+            anchor.push('-');
+        }
+    }
+    anchor.trim_matches('-').to_string()
+}
+
+fn demote_headings(md: &str, depth: usize) -> String {
+    if depth == 0 {
+        return md.to_string();
+    }
+
+    let lines = md.lines().collect::<Vec<&str>>();
+    let mut result: Vec<String> = vec![];
+    let mut fence: Option<&str> = None;
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        let trimmed = line.trim_start();
+
+        if update_fence(&mut fence, trimmed) {
+            result.push(line.to_string());
+            index += 1;
+            continue;
+        }
+
+        let hashes = trimmed.chars().take_while(|ch| *ch == '#').count();
+        if (1..=6).contains(&hashes)
+            && (trimmed[hashes..].is_empty() || trimmed[hashes..].starts_with(' '))
+        {
+            let level = (hashes + depth).min(6);
+            let heading = format!("{} {}", "#".repeat(level), trimmed[hashes..].trim());
+            result.push(heading.trim_end().to_string());
+            index += 1;
+            continue;
+        }
+
+        // Setext headings are rewritten as ATX headings since they only have two levels
+        let underline = lines.get(index + 1).map(|next| next.trim());
+        let setext_level = match underline {
+            Some(next) if !line.trim().is_empty() && !next.is_empty() => {
+                if next.chars().all(|ch| ch == '=') {
+                    Some(1)
+                } else if next.chars().all(|ch| ch == '-') {
+                    Some(2)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if let Some(level) = setext_level {
+            let level = (level + depth).min(6);
+            result.push(format!("{} {}", "#".repeat(level), line.trim()));
+            index += 2;
+            continue;
+        }
+
+        result.push(line.to_string());
+        index += 1;
+    }
+    result.join("\n")
+}
+
+/// Track fenced code blocks line by line, returns whether the line is part of one.
+fn update_fence<'a>(fence: &mut Option<&'a str>, trimmed: &'a str) -> bool {
+    if let Some(marker) = fence {
+        if trimmed.starts_with(*marker) {
+            *fence = None;
+        }
+        return true;
+    }
+    if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+        *fence = Some(&trimmed[..3]);
+        return true;
+    }
+    false
+}
+
+/// Rewrite relative links of a page in `dir`, links in fenced code blocks are kept.
+///
+/// `rewrite` gets the normalized target path and the fragment, and returns the new
+/// target or `None` to keep the link.
+pub(crate) fn rewrite_links(
+    md: &str,
+    dir: &Path,
+    rewrite: impl Fn(&Path, &str) -> Option<String>,
+) -> String {
+    let resolve = |target: &str| -> Option<String> {
+        let (path, fragment) = target.split_once('#').unwrap_or((target, ""));
+        let path = path.split('?').next().unwrap_or(path);
+        if path.is_empty() || path.contains("://") || path.starts_with('/') {
+            return None;
+        }
+        rewrite(&normalize_path(&dir.join(path)), fragment)
+    };
+
+    let mut fence = None;
+    md.lines()
+        .map(|line| {
+            if update_fence(&mut fence, line.trim_start()) {
+                line.to_string()
+            } else {
+                rewrite_line_links(line, &resolve)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn rewrite_line_links(line: &str, resolve: &impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    // Inline links and images: `](target "title")`
+    while let Some(start) = rest.find("](") {
+        result.push_str(&rest[..start + 2]);
+        rest = &rest[start + 2..];
+        let end = rest
+            .find(|ch: char| ch == ')' || ch.is_whitespace())
+            .unwrap_or(rest.len());
+        let target = rest[..end].trim_start_matches('<').trim_end_matches('>');
+        match resolve(target) {
+            Some(anchor) => result.push_str(&anchor),
+            None => result.push_str(&rest[..end]),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+
+    // Link reference definitions: `[label]: target`
+    let Some(colon) = result.find("]: ") else {
+        return result;
+    };
+    if !result.starts_with('[') {
+        return result;
+    }
+    let value = &result[colon + 3..];
+    let end = value.find(' ').unwrap_or(value.len());
+    match resolve(&value[..end]) {
+        Some(anchor) => format!("{}{}{}", &result[..colon + 3], anchor, &value[end..]),
+        None => result,
+    }
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut num_a = String::new();
+                while let Some(ch) = a.next_if(|ch| ch.is_ascii_digit()) {
+                    num_a.push(ch);
+                }
+                let mut num_b = String::new();
+                while let Some(ch) = b.next_if(|ch| ch.is_ascii_digit()) {
+                    num_b.push(ch);
+                }
+                let num_a = num_a.trim_start_matches('0');
+                let num_b = num_b.trim_start_matches('0');
+                let ordering = num_a.len().cmp(&num_b.len()).then(num_a.cmp(num_b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
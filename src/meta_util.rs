@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use htmd::HtmlToMarkdown;
+use markup5ever_rcdom::NodeData;
+use serde_json::{Map, Value};
+
+use crate::{
+    cache_util::ConverterCache,
+    config::Config,
+    config_util::write_cli_options,
+    dom_util::{parse_html, walk_elements},
+    limit_util::{convert_with_limits, ConvertLimits},
+    option_util::option_value_from_text,
+};
+
+/// Prefix of the meta tag names that set options, e.g. `<meta name="htmd:heading-style" content="setex">`.
+const META_PREFIX: &str = "htmd:";
+
+/// Converts the pages of a run, pages can set their own options with meta tags.
+pub(crate) struct PageConverter {
+    converter: Arc<HtmlToMarkdown>,
+    /// Options of the run, meta tags of a page override them.
+    options: Map<String, Value>,
+    honor_meta_options: bool,
+    /// Converters of pages with meta options or options of their own.
+    converters: ConverterCache,
+}
+
+impl PageConverter {
+    pub(crate) fn new(config: Config, honor_meta_options: bool) -> Self {
+        let options = match serde_json::to_value(write_cli_options(&config)) {
+            Ok(Value::Object(options)) => options,
+            _ => unreachable!("Options are written to a table"),
+        };
+        PageConverter {
+            converter: Arc::new(config.into_converter()),
+            options,
+            honor_meta_options,
+            converters: ConverterCache::default(),
+        }
+    }
+
+    /// Convert a page within the limits.
+    pub(crate) fn convert(&self, html: String, limits: ConvertLimits) -> Result<String, String> {
+        self.convert_with_options(html, Map::new(), limits)
+    }
+
+    /// Convert a page with options that override the options of the run, like the options
+    /// of a worker request. Meta tags of the page override both.
+    pub(crate) fn convert_with_options(
+        &self,
+        html: String,
+        options: Map<String, Value>,
+        limits: ConvertLimits,
+    ) -> Result<String, String> {
+        let converter = self.page_converter(&html, options)?;
+        convert_with_limits(&converter, html, limits)
+    }
+
+    fn page_converter(
+        &self,
+        html: &str,
+        overrides: Map<String, Value>,
+    ) -> Result<Arc<HtmlToMarkdown>, String> {
+        let meta_overrides = if self.honor_meta_options {
+            meta_options(html)?
+        } else {
+            Map::new()
+        };
+        if overrides.is_empty() && meta_overrides.is_empty() {
+            return Ok(Arc::clone(&self.converter));
+        }
+
+        let mut options = self.options.clone();
+        if !overrides.is_empty() {
+            options.extend(overrides);
+            // Checked without the meta tags, so their errors are not blamed on the page
+            let converter = self.converters.get(options.clone())?;
+            if meta_overrides.is_empty() {
+                return Ok(converter);
+            }
+        }
+        options.extend(meta_overrides);
+        self.converters
+            .get(options)
+            .map_err(|e| format!("Invalid meta options: {}", e))
+    }
+}
+
+/// Read the `htmd:` meta tags of a page into option values.
+fn meta_options(html: &str) -> Result<Map<String, Value>, String> {
+    // Meta tags are in the head, the body is not parsed if the head is closed
+    let head = match html.to_ascii_lowercase().find("</head>") {
+        Some(end) => &html[..end],
+        None => html,
+    };
+    let dom = parse_html(head)?;
+
+    let mut options = Map::new();
+    walk_elements(&dom, |node, _| {
+        let NodeData::Element { name, attrs, .. } = &node.data else {
+            return Ok(());
+        };
+        if name.local.as_ref() != "meta" {
+            return Ok(());
+        }
+        let attrs = attrs.borrow();
+        let attr = |attr_name: &str| {
+            attrs
+                .iter()
+                .find(|attr| attr.name.local.as_ref() == attr_name)
+                .map(|attr| attr.value.to_string())
+        };
+        if let (Some(name), Some(content)) = (attr("name"), attr("content")) {
+            if let Some(option_name) = name.strip_prefix(META_PREFIX) {
+                let value = option_value_from_text(option_name, content.trim())
+                    .map_err(|e| format!("Invalid meta options: {}", e))?;
+                options.insert(option_name.to_string(), value);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(options)
+}
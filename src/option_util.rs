@@ -0,0 +1,418 @@
+use htmd::options::{
+    BrStyle, BulletListMarker, CodeBlockFence, CodeBlockStyle, HeadingStyle, HrStyle,
+    LinkReferenceStyle, LinkStyle,
+};
+use serde::{de::Error, Deserialize, Deserializer};
+use serde_json::json;
+
+use crate::{
+    config::Config,
+    config_util::{write_cli_options, OptionsFormat},
+    flavor_util::Flavor,
+    math_util::MathStyle,
+    table_util::{TableFallback, TableSpan},
+};
+
+/// An enumerated option, with the names used in the cli and options files.
+pub(crate) trait OptionValue: Copy + PartialEq + 'static {
+    const VALUES: &'static [(&'static str, Self)];
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::VALUES
+            .iter()
+            .find(|(value_name, _)| *value_name == name)
+            .map(|(_, value)| *value)
+    }
+
+    fn name(self) -> &'static str {
+        Self::VALUES
+            .iter()
+            .find(|(_, value)| *value == self)
+            .map(|(name, _)| *name)
+            .expect("Every value has a name")
+    }
+
+    fn names() -> Vec<&'static str> {
+        Self::VALUES.iter().map(|(name, _)| *name).collect()
+    }
+}
+
+impl OptionValue for HeadingStyle {
+    const VALUES: &'static [(&'static str, Self)] =
+        &[("atx", HeadingStyle::Atx), ("setex", HeadingStyle::Setex)];
+}
+
+impl OptionValue for HrStyle {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("dashes", HrStyle::Dashes),
+        ("asterisks", HrStyle::Asterisks),
+        ("underscores", HrStyle::Underscores),
+    ];
+}
+
+impl OptionValue for BrStyle {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("two-spaces", BrStyle::TwoSpaces),
+        ("backslash", BrStyle::Backslash),
+    ];
+}
+
+impl OptionValue for LinkStyle {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("inlined", LinkStyle::Inlined),
+        (
+            "inlined-prefer-autolinks",
+            LinkStyle::InlinedPreferAutolinks,
+        ),
+        ("referenced", LinkStyle::Referenced),
+    ];
+}
+
+impl OptionValue for LinkReferenceStyle {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("full", LinkReferenceStyle::Full),
+        ("collapsed", LinkReferenceStyle::Collapsed),
+        ("shortcut", LinkReferenceStyle::Shortcut),
+    ];
+}
+
+impl OptionValue for CodeBlockStyle {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("fenced", CodeBlockStyle::Fenced),
+        ("indented", CodeBlockStyle::Indented),
+    ];
+}
+
+impl OptionValue for CodeBlockFence {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("backticks", CodeBlockFence::Backticks),
+        ("tildes", CodeBlockFence::Tildes),
+    ];
+}
+
+impl OptionValue for BulletListMarker {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("asterisk", BulletListMarker::Asterisk),
+        ("dash", BulletListMarker::Dash),
+    ];
+}
+
+impl OptionValue for Flavor {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("default", Flavor::Default),
+        ("obsidian", Flavor::Obsidian),
+        ("commonmark", Flavor::CommonMark),
+        ("gfm", Flavor::Gfm),
+        ("mmd", Flavor::MultiMarkdown),
+        ("php-extra", Flavor::PhpExtra),
+        ("strict", Flavor::Strict),
+    ];
+}
+
+impl OptionValue for TableFallback {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("pipe", TableFallback::Pipe),
+        ("html", TableFallback::Html),
+        ("list", TableFallback::List),
+        ("text", TableFallback::Text),
+    ];
+}
+
+impl OptionValue for TableSpan {
+    const VALUES: &'static [(&'static str, Self)] =
+        &[("empty", TableSpan::Empty), ("repeat", TableSpan::Repeat)];
+}
+
+impl OptionValue for MathStyle {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("none", MathStyle::None),
+        ("dollars", MathStyle::Dollars),
+        ("brackets", MathStyle::Brackets),
+        ("code", MathStyle::Code),
+    ];
+}
+
+impl OptionValue for OptionsFormat {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("toml", OptionsFormat::Toml),
+        ("yaml", OptionsFormat::Yaml),
+        ("json", OptionsFormat::Json),
+    ];
+}
+
+/// Deserialize an optional enumerated option from its name.
+pub(crate) fn deserialize_value<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: OptionValue,
+{
+    let Some(name) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    T::parse(&name).map(Some).ok_or_else(|| {
+        let names = T::names();
+        let expected = names
+            .iter()
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<String>>()
+            .join(", ");
+        D::Error::custom(format!(
+            "unknown value `{}`, expected one of {}{}",
+            name,
+            expected,
+            did_you_mean(&name, &names)
+        ))
+    })
+}
+
+/// A ", did you mean `x`?" hint for the closest of `candidates`, or an empty string.
+pub(crate) fn did_you_mean(name: &str, candidates: &[&str]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), candidate))
+        .filter(|(confidence, _)| *confidence > 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| format!(", did you mean `{}`?", candidate))
+        .unwrap_or_default()
+}
+
+/// The type of an option in options files.
+pub(crate) enum OptionKind {
+    /// One of the names of an [OptionValue].
+    Value(fn() -> Vec<&'static str>),
+    Bool,
+    U8,
+    /// A list of strings, separated by commas in the cli.
+    List,
+}
+
+/// An option of the `[options]` table.
+pub(crate) struct OptionDef {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: OptionKind,
+}
+
+/// Every option of the `[options]` table, in the order they are documented.
+pub(crate) const OPTIONS: [OptionDef; 22] = [
+    OptionDef {
+        name: "heading-style",
+        help: "Heading style: atx (# Heading) or setex (underlined)",
+        kind: OptionKind::Value(HeadingStyle::names),
+    },
+    OptionDef {
+        name: "hr-style",
+        help: "Horizontal rule style",
+        kind: OptionKind::Value(HrStyle::names),
+    },
+    OptionDef {
+        name: "br-style",
+        help: "Line break style: two trailing spaces or a backslash",
+        kind: OptionKind::Value(BrStyle::names),
+    },
+    OptionDef {
+        name: "link-style",
+        help: "Link style; referenced links are listed at the end of the document",
+        kind: OptionKind::Value(LinkStyle::names),
+    },
+    OptionDef {
+        name: "link-reference-style",
+        help: "Reference style of referenced links",
+        kind: OptionKind::Value(LinkReferenceStyle::names),
+    },
+    OptionDef {
+        name: "code-block-style",
+        help: "Code block style",
+        kind: OptionKind::Value(CodeBlockStyle::names),
+    },
+    OptionDef {
+        name: "code-block-fence",
+        help: "Fence of fenced code blocks",
+        kind: OptionKind::Value(CodeBlockFence::names),
+    },
+    OptionDef {
+        name: "code-language-classes",
+        help: "Class patterns of the language of code blocks, '*' is the language,\n\
+            e.g. 'language-*' for <code class=\"language-rust\">",
+        kind: OptionKind::List,
+    },
+    OptionDef {
+        name: "code-line-numbers",
+        help: "Keep the line numbers of highlighted code blocks",
+        kind: OptionKind::Bool,
+    },
+    OptionDef {
+        name: "bullet-list-marker",
+        help: "Bullet list marker",
+        kind: OptionKind::Value(BulletListMarker::names),
+    },
+    OptionDef {
+        name: "preformatted-code",
+        help: "Preserve whitespace in inline code tags",
+        kind: OptionKind::Bool,
+    },
+    OptionDef {
+        name: "ul-bullet-spacing",
+        help: "Spaces between ul bullet chars and contents",
+        kind: OptionKind::U8,
+    },
+    OptionDef {
+        name: "ol-number-spacing",
+        help: "Spaces between ol period chars and contents",
+        kind: OptionKind::U8,
+    },
+    OptionDef {
+        name: "table-fallback",
+        help: "What tables that cannot be pipe tables become, like tables without a\n\
+            header row, nested tables, or cells with lists: a pipe table with the cells\n\
+            flattened, raw html, a list of the rows, or the text of the cells",
+        kind: OptionKind::Value(TableFallback::names),
+    },
+    OptionDef {
+        name: "table-span",
+        help: "Fill the cells covered by colspan and rowspan with empty cells or\n\
+            repeat the content of the spanning cell",
+        kind: OptionKind::Value(TableSpan::names),
+    },
+    OptionDef {
+        name: "table-alignment",
+        help: "Align table columns by the align attribute or text-align style of their cells",
+        kind: OptionKind::Bool,
+    },
+    OptionDef {
+        name: "table-padding",
+        help: "Pad table cells to the width of their column",
+        kind: OptionKind::Bool,
+    },
+    OptionDef {
+        name: "math",
+        help: "Recover math from MathJax scripts, KaTeX, and MathML and write it as\n\
+            $x$ and $$x$$, \\(x\\) and \\[x\\], or $`x`$ and ```math blocks; 'none'\n\
+            converts them like other elements",
+        kind: OptionKind::Value(MathStyle::names),
+    },
+    OptionDef {
+        name: "ignored-tags",
+        help: "HTML tags to skip, like script and style",
+        kind: OptionKind::List,
+    },
+    OptionDef {
+        name: "flatten-output",
+        help: "Write all output files into the output folder, without sub folders",
+        kind: OptionKind::Bool,
+    },
+    OptionDef {
+        name: "scripting-enabled",
+        help: "Option for HTML parsing. Set to false to parse contents in <noscript>",
+        kind: OptionKind::Bool,
+    },
+    OptionDef {
+        name: "flavor",
+        help: "Markdown flavor of the output. 'obsidian' writes wiki links between\n\
+            converted pages, embedded images, and callouts from admonition blocks.\n\
+            'commonmark', 'gfm', 'mmd', 'php-extra', and 'strict' write only the syntax\n\
+            of that dialect and keep the rest as raw HTML; they also change the\n\
+            defaults of other options, e.g. 'strict' indents code blocks",
+        kind: OptionKind::Value(Flavor::names),
+    },
+];
+
+/// Read a boolean from text, the same for flags, `HTMD_*` variables, query parameters and
+/// meta tags: true/false, 1/0, yes/no, on/off, and y/n or t/f.
+pub(crate) fn parse_bool(text: &str) -> Result<bool, String> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" | "y" | "t" => Ok(true),
+        "false" | "0" | "no" | "off" | "n" | "f" => Ok(false),
+        _ => Err(format!(
+            "{:?} is not a boolean, use true/false, 1/0, yes/no, or on/off",
+            text
+        )),
+    }
+}
+
+/// Type the text of an option by its kind, for query parameters and meta tags.
+///
+/// Lists are separated by commas. Unknown names are kept as strings, they are reported
+/// when the options are read.
+pub(crate) fn option_value_from_text(name: &str, text: &str) -> Result<serde_json::Value, String> {
+    let Some(option) = OPTIONS.iter().find(|option| option.name == name) else {
+        return Ok(json!(text));
+    };
+    let invalid = || format!("Invalid value of option {}: {:?}", name, text);
+    Ok(match option.kind {
+        OptionKind::Value(_) => json!(text),
+        OptionKind::Bool => json!(parse_bool(text).map_err(|_| invalid())?),
+        OptionKind::U8 => json!(text.parse::<u8>().map_err(|_| invalid())?),
+        OptionKind::List => json!(text
+            .split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .collect::<Vec<&str>>()),
+    })
+}
+
+/// JSON Schema of the `[options]` table, defaults are the defaults of [Config].
+pub(crate) fn options_schema() -> serde_json::Value {
+    let defaults = serde_json::to_value(write_cli_options(&Config::default()))
+        .expect("Options are valid JSON");
+
+    let mut properties = serde_json::Map::new();
+    for option in &OPTIONS {
+        let description = option.help.replace('\n', " ");
+        let mut schema = match option.kind {
+            OptionKind::Value(names) => json!({ "type": "string", "enum": names() }),
+            OptionKind::Bool => json!({ "type": "boolean" }),
+            OptionKind::U8 => json!({ "type": "integer", "minimum": 0, "maximum": u8::MAX }),
+            OptionKind::List => json!({ "type": "array", "items": { "type": "string" } }),
+        };
+        schema["description"] = json!(description);
+        if let Some(default) = defaults.get(option.name) {
+            schema["default"] = default.clone();
+        }
+        properties.insert(option.name.to_string(), schema);
+    }
+    json!({
+        "type": "object",
+        "description": "Conversion options, the same as the cli options",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// JSON Schema of an options file, for editors.
+pub(crate) fn options_file_schema() -> serde_json::Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "htmd options file",
+        "type": "object",
+        "properties": { "options": options_schema() },
+        "required": ["options"],
+        "additionalProperties": false,
+    })
+}
+
+/// Starting points for `htmd config init`.
+pub(crate) const PROFILES: [&str; 3] = ["default", "gfm", "obsidian"];
+
+/// The options of a profile in [PROFILES].
+pub(crate) fn profile_config(profile: &str) -> Config {
+    let mut config = Config::default();
+    match profile {
+        // The syntax GitHub uses when it writes Markdown
+        "gfm" => {
+            config.flavor = Flavor::Gfm;
+            let options = &mut config.converter_options;
+            options.hr_style = HrStyle::Dashes;
+            options.bullet_list_marker = BulletListMarker::Dash;
+            options.ul_bullet_spacing = 1;
+            options.ol_number_spacing = 1;
+            config.ignored_tags = Some(vec!["script".to_string(), "style".to_string()]);
+        }
+        "obsidian" => {
+            config.flavor = Flavor::Obsidian;
+            config.converter_options.bullet_list_marker = BulletListMarker::Dash;
+        }
+        _ => {}
+    }
+    config
+}
@@ -0,0 +1,153 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use clap::ArgMatches;
+
+use crate::{batch::FileEvent, cli_options::is_overridden};
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub(crate) enum Verbosity {
+    /// Only errors are printed.
+    Quiet,
+    Normal,
+    /// Log every converted file.
+    Verbose,
+    /// Log every converted file with its timing.
+    Debug,
+}
+
+pub(crate) fn resolve_verbosity(matches: &ArgMatches) -> Verbosity {
+    if matches.get_flag("quiet") && !is_overridden(matches, "quiet") {
+        return Verbosity::Quiet;
+    }
+    if is_overridden(matches, "verbose") {
+        return Verbosity::Normal;
+    }
+    match matches.get_count("verbose") {
+        0 => Verbosity::Normal,
+        1 => Verbosity::Verbose,
+        _ => Verbosity::Debug,
+    }
+}
+
+/// Reports the progress of a batch conversion on stderr.
+///
+/// The bar is only drawn when both stdout and stderr are terminals, logs of
+/// converted files are printed above the bar.
+pub(crate) struct Progress {
+    verbosity: Verbosity,
+    draw_bar: bool,
+    start: Instant,
+    last_draw: Mutex<Option<Instant>>,
+}
+
+const DRAW_INTERVAL: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 30;
+
+impl Progress {
+    pub fn new(verbosity: Verbosity) -> Self {
+        let draw_bar = verbosity != Verbosity::Quiet
+            && io::stdout().is_terminal()
+            && io::stderr().is_terminal();
+        Progress {
+            verbosity,
+            draw_bar,
+            start: Instant::now(),
+            last_draw: Mutex::new(None),
+        }
+    }
+
+    /// Record a finished file.
+    pub fn file_done(&self, event: &FileEvent) {
+        // Also keeps the lines of different workers apart
+        let mut last_draw = self.last_draw.lock().unwrap();
+
+        let mut stderr = io::stderr().lock();
+        if self.verbosity >= Verbosity::Verbose {
+            if self.draw_bar {
+                // Clear the bar before logging
+                let _ = write!(stderr, "\r\x1b[2K");
+            }
+            let mut line = match event.result {
+                Ok(page) => format!(
+                    "converted input={:?} output={:?}",
+                    event.input.to_string_lossy(),
+                    page.output.to_string_lossy()
+                ),
+                Err(failure) => format!(
+                    "failed input={:?} reason={:?}",
+                    event.input.to_string_lossy(),
+                    failure.reason
+                ),
+            };
+            if self.verbosity >= Verbosity::Debug {
+                line.push_str(&format!(
+                    " elapsed_ms={:.3}",
+                    event.elapsed.as_secs_f64() * 1000.0
+                ));
+            }
+            let _ = writeln!(stderr, "{}", line);
+        }
+
+        if !self.draw_bar {
+            return;
+        }
+        let now = Instant::now();
+        let is_last = event.done == event.total;
+        let should_draw = self.verbosity >= Verbosity::Verbose
+            || is_last
+            || last_draw.is_none_or(|last_draw| now - last_draw >= DRAW_INTERVAL);
+        if should_draw {
+            *last_draw = Some(now);
+            let _ = write!(stderr, "\r\x1b[2K{}", self.bar(event.done, event.total));
+            let _ = stderr.flush();
+        }
+    }
+
+    /// Clear the bar, the summary line is printed by the caller.
+    pub fn finish(&self) {
+        if self.draw_bar {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+
+    fn bar(&self, done: usize, total: usize) -> String {
+        let filled = BAR_WIDTH * done / total.max(1);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = if rate > 0.0 {
+            format_secs(total.saturating_sub(done) as f64 / rate)
+        } else {
+            "-".to_string()
+        };
+        format!(
+            "[{}{}] {}/{} {:.1} files/s ETA {}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            done,
+            total,
+            rate,
+            eta
+        )
+    }
+}
+
+fn format_secs(secs: f64) -> String {
+    let secs = secs.ceil() as u64;
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    process::exit,
+    sync::Arc,
+    thread,
+};
+
+use clap::{value_parser, Arg, ArgMatches, Command};
+use serde_json::{Map, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    config::Config, config_util::read_cli_options_from_json, flavor_util::decode_percent,
+    io_util::parse_size, option_util::option_value_from_text, pool_util::resolve_jobs,
+};
+
+const DEFAULT_MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+pub(crate) fn serve_command() -> Command {
+    Command::new("serve")
+        .about("Serve conversion as a local HTTP API")
+        .args([
+            Arg::new("listen")
+                .long("listen")
+                .help("Address to listen on, use port 0 to pick a free port")
+                .num_args(1)
+                .default_value("127.0.0.1:8080"),
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .help("Number of requests to handle concurrently; defaults to the number of CPUs")
+                .num_args(1)
+                .value_parser(value_parser!(u32).range(1..)),
+            Arg::new("max-body-size")
+                .long("max-body-size")
+                .help("Maximum size of a request body, e.g. 512K or 10M; defaults to 10M")
+                .num_args(1)
+                .value_parser(parse_size),
+        ])
+}
+
+/// Run the HTTP server until the process is killed.
+///
+/// - `GET /health`: returns `{"status":"ok"}`
+/// - `POST /convert`: the body is the HTML and options are query parameters, or the
+///   body is `{"html": "...", "options": {...}}` with a JSON content type.
+///   Option names are the same as the cli options.
+pub(crate) fn serve(matches: &ArgMatches) {
+    let listen = matches.get_one::<String>("listen").unwrap();
+    let jobs = resolve_jobs(matches);
+    let max_body_size = matches
+        .get_one::<u64>("max-body-size")
+        .copied()
+        .unwrap_or(DEFAULT_MAX_BODY_SIZE);
+
+    let server = Server::http(listen).unwrap_or_else(|e| {
+        eprintln!("Cannot listen on {}: {}", listen, e);
+        exit(1);
+    });
+    let server = Arc::new(server);
+
+    println!("Listening on http://{}", server.server_addr());
+    io::stdout().flush().expect("Failed to write to stdout");
+
+    // Every worker handles one request at a time, others wait in the queue
+    let workers = (0..jobs)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            thread::spawn(move || loop {
+                match server.recv() {
+                    Ok(request) => handle_request(request, max_body_size),
+                    Err(e) => eprintln!("Failed to receive request: {}", e),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+fn handle_request(mut request: Request, max_body_size: u64) {
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+
+    let response = match (request.method(), path.as_str()) {
+        (Method::Get, "/health") => json_response(200, r#"{"status":"ok"}"#),
+        (Method::Post, "/convert") => match convert_request(&mut request, &query, max_body_size) {
+            Ok(md) => {
+                Response::from_string(md).with_header(content_type("text/markdown; charset=utf-8"))
+            }
+            Err((status, message)) => error_response(status, &message),
+        },
+        (_, "/health") | (_, "/convert") => error_response(405, "Method not allowed"),
+        _ => error_response(404, "Not found"),
+    };
+
+    if let Err(e) = request.respond(response) {
+        eprintln!("Failed to send response: {}", e);
+    }
+}
+
+fn convert_request(
+    request: &mut Request,
+    query: &str,
+    max_body_size: u64,
+) -> Result<String, (u16, String)> {
+    if request
+        .body_length()
+        .is_some_and(|length| length as u64 > max_body_size)
+    {
+        return Err(too_large(max_body_size));
+    }
+    let mut body: Vec<u8> = vec![];
+    request
+        .as_reader()
+        .take(max_body_size.saturating_add(1))
+        .read_to_end(&mut body)
+        .map_err(|e| (400, format!("Cannot read request body: {}", e)))?;
+    if body.len() as u64 > max_body_size {
+        return Err(too_large(max_body_size));
+    }
+    let body = String::from_utf8(body).map_err(|_| (400, "Body is not UTF-8 text".to_string()))?;
+
+    let is_json = request.headers().iter().any(|header| {
+        header.field.equiv("Content-Type")
+            && header
+                .value
+                .as_str()
+                .to_ascii_lowercase()
+                .starts_with("application/json")
+    });
+    let (html, options) = if is_json {
+        parse_json_body(&body)?
+    } else {
+        let options = options_from_query(query).map_err(|e| (400, e))?;
+        (body, read_cli_options_from_json(&options))
+    };
+
+    let options = options.map_err(|e| (400, e.to_string()))?;
+    options
+        .into_converter()
+        .convert(&html)
+        .map_err(|e| (500, format!("Failed to convert: {}", e)))
+}
+
+type ReadOptionsResult = Result<Config, Box<dyn Error>>;
+
+fn parse_json_body(body: &str) -> Result<(String, ReadOptionsResult), (u16, String)> {
+    let json: Value =
+        serde_json::from_str(body).map_err(|e| (400, format!("Invalid JSON: {}", e)))?;
+    let html = json
+        .get("html")
+        .and_then(|html| html.as_str())
+        .ok_or_else(|| (400, "\"html\" must be a string".to_string()))?;
+    let options = read_cli_options_from_json(json.get("options").unwrap_or_default());
+    Ok((html.to_string(), options))
+}
+
+/// Map `?heading-style=setex&ignored-tags=script,style` to an options object, values are
+/// typed by their option.
+fn options_from_query(query: &str) -> Result<Value, String> {
+    let mut options = Map::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, "true"));
+        let name = decode_percent(&name.replace('+', " "));
+        let value = decode_percent(&value.replace('+', " "));
+        let value = option_value_from_text(&name, &value)?;
+        options.insert(name, value);
+    }
+    Ok(Value::Object(options))
+}
+
+fn too_large(max_body_size: u64) -> (u16, String) {
+    (
+        413,
+        format!("Request body is larger than {} bytes", max_body_size),
+    )
+}
+
+fn error_response(status: u16, message: &str) -> Response<io::Cursor<Vec<u8>>> {
+    let body = format!("{{\"error\":{}}}", Value::from(message));
+    json_response(status, &body)
+}
+
+fn json_response(status: u16, body: &str) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(content_type("application/json"))
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).unwrap()
+}
@@ -0,0 +1,452 @@
+use std::rc::Rc;
+
+use htmd::{
+    element_handler::{HandlerResult, Handlers},
+    options::BulletListMarker,
+    Element, HtmlToMarkdownBuilder,
+};
+use markup5ever_rcdom::Node;
+
+use crate::flavor_util::{node_attr, raw_html, tag_name, Flavor};
+
+/// What tables that cannot be pipe tables become, like nested tables or cells with lists.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TableFallback {
+    /// A pipe table anyway, the content of complex cells is flattened into their row and
+    /// tables without a header row become text, like htmd's own table handler.
+    Pipe,
+    /// Keep the table as raw HTML.
+    Html,
+    /// A list item for every row, with the cells under their headers.
+    List,
+    /// The text of the cells as paragraphs.
+    Text,
+}
+
+/// How the cells covered by `colspan` and `rowspan` are filled.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TableSpan {
+    Empty,
+    /// Repeat the content of the spanning cell.
+    Repeat,
+}
+
+/// Conversion options of tables.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TableOptions {
+    pub fallback: TableFallback,
+    pub span: TableSpan,
+    /// Align columns by the `align` attribute or the `text-align` style of their cells.
+    pub alignment: bool,
+    /// Pad cells to the width of their column.
+    pub padding: bool,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            fallback: TableFallback::Pipe,
+            span: TableSpan::Empty,
+            alignment: false,
+            padding: true,
+        }
+    }
+}
+
+/// Elements in cells that a pipe table cannot hold.
+const COMPLEX_TAGS: [&str; 13] = [
+    "table",
+    "ul",
+    "ol",
+    "pre",
+    "blockquote",
+    "dl",
+    "hr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+/// The largest `colspan` and `rowspan`, the same limits as browsers.
+const MAX_COLSPAN: usize = 1000;
+const MAX_ROWSPAN: usize = 65534;
+
+pub(crate) fn apply_table_options(
+    builder: HtmlToMarkdownBuilder,
+    options: TableOptions,
+    flavor: Flavor,
+) -> HtmlToMarkdownBuilder {
+    let pipe_tables = flavor.has_pipe_tables();
+    builder.add_handler(
+        vec!["table"],
+        move |handlers: &dyn Handlers, element: Element| {
+            table_handler(handlers, element, options, pipe_tables)
+        },
+    )
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// A table read into a grid, spanning cells are already filled in.
+struct Table {
+    captions: Vec<String>,
+    /// Rows of the header, pipe tables have exactly one.
+    header_rows: usize,
+    rows: Vec<Vec<String>>,
+    alignments: Vec<Option<Alignment>>,
+    /// Whether cells have content that a pipe table cannot hold.
+    complex: bool,
+}
+
+fn table_handler(
+    handlers: &dyn Handlers,
+    element: Element,
+    options: TableOptions,
+    pipe_tables: bool,
+) -> Option<HandlerResult> {
+    let table = read_table(handlers, element.node, options);
+    if table.rows.is_empty() {
+        return handlers.fallback(element);
+    }
+
+    if pipe_tables && table.header_rows == 1 && !table.complex {
+        return Some(pipe_table(&table, options).into());
+    }
+    let markdown = match options.fallback {
+        TableFallback::Pipe => return handlers.fallback(element),
+        TableFallback::Html => raw_html(element.node),
+        TableFallback::List => list_table(handlers, &table),
+        TableFallback::Text => handlers
+            .walk_children(element.node)
+            .content
+            .trim_matches('\n')
+            .to_string(),
+    };
+    Some(format!("\n\n{}\n\n", markdown).into())
+}
+
+fn read_table(handlers: &dyn Handlers, node: &Rc<Node>, options: TableOptions) -> Table {
+    let mut captions = vec![];
+    let mut head = vec![];
+    let mut body = vec![];
+    for child in node.children.borrow().iter() {
+        match tag_name(child) {
+            Some("caption") => {
+                let caption = handlers.walk_children(child).content;
+                captions.push(caption.trim().to_string());
+            }
+            Some("thead") => head.extend(child_elements(child, &["tr"])),
+            Some("tbody" | "tfoot") => body.extend(child_elements(child, &["tr"])),
+            Some("tr") => body.push(Rc::clone(child)),
+            _ => {}
+        }
+    }
+    // Without a `thead`, a first row of only `th` cells is the header
+    if head.is_empty() {
+        let first_row_is_header = body.first().is_some_and(|row| {
+            let cells = child_elements(row, &["td", "th"]);
+            !cells.is_empty() && cells.iter().all(|cell| tag_name(cell) == Some("th"))
+        });
+        if first_row_is_header {
+            head.push(body.remove(0));
+        }
+    }
+
+    let header_rows = head.len();
+    let row_nodes = head.into_iter().chain(body).collect::<Vec<Rc<Node>>>();
+
+    let mut rows: Vec<Vec<String>> = vec![];
+    let mut alignments: Vec<Option<Alignment>> = vec![];
+    // Cells of earlier rows that span down: the rows left and the content
+    let mut spans: Vec<(usize, String)> = vec![];
+    for (row_index, row_node) in row_nodes.iter().enumerate() {
+        let mut row = vec![];
+        let mut cells = child_elements(row_node, &["td", "th"]).into_iter();
+        loop {
+            let column = row.len();
+            if let Some((rows_left, content)) = spans.get_mut(column).filter(|span| span.0 > 0) {
+                *rows_left -= 1;
+                row.push(match options.span {
+                    TableSpan::Repeat => content.clone(),
+                    TableSpan::Empty => String::new(),
+                });
+                continue;
+            }
+            let Some(cell) = cells.next() else {
+                // Cells that span down may still follow the cells of this row
+                if spans.iter().skip(column).any(|span| span.0 > 0) {
+                    row.push(String::new());
+                    continue;
+                }
+                break;
+            };
+
+            let content = handlers.walk_children(&cell).content.trim().to_string();
+            let colspan = span_attr(&cell, "colspan").clamp(1, MAX_COLSPAN);
+            // `rowspan="0"` spans to the last row
+            let rowspan = match span_attr(&cell, "rowspan") {
+                0 => row_nodes.len() - row_index,
+                rowspan => rowspan.min(MAX_ROWSPAN),
+            };
+            let alignment = cell_alignment(&cell);
+            for offset in 0..colspan {
+                let content = if offset == 0 || options.span == TableSpan::Repeat {
+                    content.clone()
+                } else {
+                    String::new()
+                };
+                let column = row.len();
+                if spans.len() <= column {
+                    spans.resize(column + 1, (0, String::new()));
+                    alignments.resize(column + 1, None);
+                }
+                spans[column] = (rowspan - 1, content.clone());
+                if alignments[column].is_none() {
+                    alignments[column] = alignment;
+                }
+                row.push(content);
+            }
+        }
+        rows.push(row);
+    }
+
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    for row in rows.iter_mut() {
+        row.resize(columns, String::new());
+    }
+    alignments.resize(columns, None);
+    if !options.alignment {
+        alignments.fill(None);
+    }
+
+    Table {
+        captions,
+        header_rows,
+        rows,
+        alignments,
+        complex: has_complex_content(node),
+    }
+}
+
+fn pipe_table(table: &Table, options: TableOptions) -> String {
+    let rows = table
+        .rows
+        .iter()
+        .map(|row| row.iter().map(|cell| pipe_cell(cell)).collect())
+        .collect::<Vec<Vec<String>>>();
+
+    let widths = table
+        .alignments
+        .iter()
+        .enumerate()
+        .map(|(column, alignment)| {
+            // Room for the colons of the separator
+            let separator_width = match (alignment, options.padding) {
+                (_, false) => 3,
+                (Some(Alignment::Center), true) => 3,
+                (Some(_), true) => 2,
+                (None, true) => 1,
+            };
+            let content_width = rows
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0);
+            if options.padding {
+                content_width.max(separator_width)
+            } else {
+                separator_width
+            }
+        })
+        .collect::<Vec<usize>>();
+
+    let mut markdown = String::from("\n\n");
+    for caption in &table.captions {
+        markdown.push_str(caption);
+        markdown.push('\n');
+    }
+    for (index, row) in rows.iter().enumerate() {
+        markdown.push('|');
+        for ((cell, width), alignment) in row.iter().zip(&widths).zip(&table.alignments) {
+            markdown.push(' ');
+            if options.padding {
+                markdown.push_str(&pad_cell(cell, *width, *alignment));
+            } else {
+                markdown.push_str(cell);
+            }
+            markdown.push_str(" |");
+        }
+        markdown.push('\n');
+
+        if index == 0 {
+            markdown.push('|');
+            for (width, alignment) in widths.iter().zip(&table.alignments) {
+                markdown.push(' ');
+                markdown.push_str(&separator_cell(*width, *alignment));
+                markdown.push_str(" |");
+            }
+            markdown.push('\n');
+        }
+    }
+    markdown.push('\n');
+    markdown
+}
+
+/// Put a cell on one line, line breaks become `<br>`, and escape pipes.
+fn pipe_cell(content: &str) -> String {
+    let lines = content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>();
+    let last = lines.len().saturating_sub(1);
+    lines
+        .iter()
+        .enumerate()
+        // A backslash line break doesn't end the line of a cell
+        .map(|(index, line)| match line.strip_suffix('\\') {
+            Some(line) if index < last => line.trim_end(),
+            _ => line,
+        })
+        .collect::<Vec<&str>>()
+        .join("<br>")
+        .replace('|', "\\|")
+}
+
+fn pad_cell(cell: &str, width: usize, alignment: Option<Alignment>) -> String {
+    let pad = width.saturating_sub(cell.chars().count());
+    match alignment {
+        Some(Alignment::Right) => format!("{}{}", " ".repeat(pad), cell),
+        Some(Alignment::Center) => {
+            format!(
+                "{}{}{}",
+                " ".repeat(pad / 2),
+                cell,
+                " ".repeat(pad - pad / 2)
+            )
+        }
+        _ => format!("{}{}", cell, " ".repeat(pad)),
+    }
+}
+
+fn separator_cell(width: usize, alignment: Option<Alignment>) -> String {
+    match alignment {
+        Some(Alignment::Left) => format!(":{}", "-".repeat(width - 1)),
+        Some(Alignment::Right) => format!("{}:", "-".repeat(width - 1)),
+        Some(Alignment::Center) => format!(":{}:", "-".repeat(width - 2)),
+        None => "-".repeat(width),
+    }
+}
+
+/// A list item for every row, the first cell is the item and the others are listed under it.
+fn list_table(handlers: &dyn Handlers, table: &Table) -> String {
+    let options = handlers.options();
+    let marker = match options.bullet_list_marker {
+        BulletListMarker::Asterisk => "*",
+        BulletListMarker::Dash => "-",
+    };
+    let spacing = usize::from(options.ul_bullet_spacing).max(1);
+
+    let (header, rows) = match table.header_rows {
+        0 => (None, &table.rows[..]),
+        count => (table.rows.get(count - 1), &table.rows[count..]),
+    };
+    let label = |column: usize, content: &str| match header.map(|header| header[column].as_str()) {
+        Some(name) if !name.is_empty() => {
+            // A label on its own line keeps blocks like lists at the start of a line
+            let separator = if content.contains('\n') { "\n\n" } else { " " };
+            format!("{}:{}{}", name.replace('\n', " "), separator, content)
+        }
+        _ => content.to_string(),
+    };
+
+    let mut items = vec![];
+    for row in rows {
+        let mut cells = row
+            .iter()
+            .enumerate()
+            .filter(|(_, content)| !content.is_empty())
+            .map(|(column, content)| label(column, content));
+        let Some(first) = cells.next() else {
+            continue;
+        };
+        let nested = cells
+            .map(|cell| list_item(marker, spacing, &cell))
+            .collect::<Vec<String>>();
+        let mut item = first;
+        if !nested.is_empty() {
+            item.push('\n');
+            item.push_str(&nested.join("\n"));
+        }
+        items.push(list_item(marker, spacing, &item));
+    }
+
+    let mut list = table.captions.join("\n");
+    if !list.is_empty() {
+        list.push_str("\n\n");
+    }
+    list.push_str(&items.join("\n"));
+    list
+}
+
+fn list_item(marker: &str, spacing: usize, content: &str) -> String {
+    let indent = " ".repeat(marker.len() + spacing);
+    let mut item = format!("{}{}", marker, " ".repeat(spacing));
+    for (index, line) in content.lines().enumerate() {
+        if index > 0 {
+            item.push('\n');
+            if !line.is_empty() {
+                item.push_str(&indent);
+            }
+        }
+        item.push_str(line);
+    }
+    item
+}
+
+fn has_complex_content(node: &Rc<Node>) -> bool {
+    node.children.borrow().iter().any(|child| {
+        tag_name(child).is_some_and(|tag| COMPLEX_TAGS.contains(&tag)) || has_complex_content(child)
+    })
+}
+
+/// The alignment of a cell, from the `text-align` style or the `align` attribute.
+fn cell_alignment(cell: &Rc<Node>) -> Option<Alignment> {
+    let style_alignment = node_attr(cell, "style").and_then(|style| {
+        style.split(';').find_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            (property.trim().eq_ignore_ascii_case("text-align")).then(|| value.trim().to_string())
+        })
+    });
+    let alignment = style_alignment.or_else(|| node_attr(cell, "align"))?;
+    match alignment.to_ascii_lowercase().as_str() {
+        "left" | "start" => Some(Alignment::Left),
+        "center" => Some(Alignment::Center),
+        "right" | "end" => Some(Alignment::Right),
+        _ => None,
+    }
+}
+
+/// A `colspan` or `rowspan`, 1 if it is missing or invalid.
+fn span_attr(cell: &Rc<Node>, name: &str) -> usize {
+    node_attr(cell, name)
+        .and_then(|span| span.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+fn child_elements(node: &Rc<Node>, tags: &[&str]) -> Vec<Rc<Node>> {
+    node.children
+        .borrow()
+        .iter()
+        .filter(|child| tag_name(child).is_some_and(|tag| tags.contains(&tag)))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,55 @@
+use std::io::{self, BufRead, BufWriter, Write};
+
+use serde_json::{Map, Value};
+
+use crate::{limit_util::ConvertLimits, meta_util::PageConverter};
+
+/// Serve newline-delimited JSON requests from stdin until it is closed.
+///
+/// Every `{"id": ..., "html": "...", "options": {...}}` line gets a `{"id": ..., "markdown": "..."}`
+/// or `{"id": ..., "error": "..."}` line in return. `options` are the same as the cli options,
+/// they override the options of the run.
+pub(crate) fn run_stdio_worker(converter: &PageConverter, limits: ConvertLimits) {
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("Cannot read text from stdin.");
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (id, result) = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or_default();
+                (id, handle_request(&request, converter, limits))
+            }
+            Err(e) => (Value::Null, Err(format!("Invalid JSON: {}", e))),
+        };
+        let (key, value) = match result {
+            Ok(md) => ("markdown", md),
+            Err(message) => ("error", message),
+        };
+        // Written by hand to keep `id` first
+        writeln!(out, "{{\"id\":{},\"{}\":{}}}", id, key, Value::from(value))
+            .and_then(|_| out.flush())
+            .expect("Failed to write to stdout");
+    }
+}
+
+fn handle_request(
+    request: &Value,
+    converter: &PageConverter,
+    limits: ConvertLimits,
+) -> Result<String, String> {
+    let html = request
+        .get("html")
+        .and_then(|html| html.as_str())
+        .ok_or_else(|| "\"html\" must be a string".to_string())?;
+    let options = match request.get("options") {
+        None | Some(Value::Null) => Map::new(),
+        Some(Value::Object(options)) => options.clone(),
+        Some(_) => return Err("\"options\" must be an object".to_string()),
+    };
+
+    converter.convert_with_options(html.to_string(), options, limits)
+}
@@ -2,9 +2,10 @@
 mod tests {
     use std::{
         env, fs,
-        io::{self, Write},
+        io::{self, BufRead, BufReader, Read, Write},
+        net::TcpStream,
         path::{Path, PathBuf},
-        process::{Command, Stdio},
+        process::{Child, Command, Stdio},
     };
 
     struct ExecResult {
@@ -15,8 +16,189 @@ mod tests {
 
     #[test]
     fn print_version() {
-        let result = exec(vec!["-v"]);
-        assert_eq!(env!("CARGO_PKG_VERSION"), result.stdout.trim());
+        for flag in ["--version", "-V"] {
+            let result = exec(vec![flag]);
+            assert_eq!(
+                format!("htmd {}", env!("CARGO_PKG_VERSION")),
+                result.stdout.trim()
+            );
+        }
+    }
+
+    #[test]
+    fn print_completions_and_man_page() {
+        let result = exec(vec!["completions", "bash"]);
+        assert_eq!(result.exit_code, 0);
+        assert!(result.stdout.contains("_htmd()"));
+        assert!(result.stdout.contains("--heading-style"));
+        assert!(result.stdout.contains("atx setex"));
+
+        let result = exec(vec!["completions", "nushell"]);
+        assert_ne!(result.exit_code, 0);
+
+        let result = exec(vec!["man"]);
+        assert_eq!(result.exit_code, 0);
+        assert!(result.stdout.starts_with(".ie"));
+        assert!(result.stdout.contains(".TH htmd 1"));
+        assert!(result.stdout.contains("heading\\-style"));
+    }
+
+    #[test]
+    fn stdin_in_convert_subcommand() {
+        let html = "<h1>Hello</h1>";
+        let result = exec_with_input(Some(html), vec!["convert", "--heading-style", "setex"]);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!("Hello\n=====", result.stdout);
+    }
+
+    #[test]
+    fn test_config_subcommands() {
+        let result = exec_with_temp_fs(vec!["config", "init"], |dir| {
+            let text = fs::read_to_string(dir.join(".htmd.toml")).unwrap();
+            assert!(text.contains("\n[options]\n"));
+            assert!(text.contains(
+                "# Values: \"atx\", \"setex\"; default: \"atx\"\nheading-style = \"atx\"\n"
+            ));
+            assert!(text.contains("# ignored-tags = [\"script\", \"style\"]\n"));
+            htmd_cli::Config::from_toml(&text).unwrap();
+        });
+        assert_eq!(result.exit_code, 0);
+
+        let result = exec_with_temp_fs(
+            vec!["config", "init", "--profile", "gfm", "gfm.toml"],
+            |dir| {
+                let text = fs::read_to_string(dir.join("gfm.toml")).unwrap();
+                assert!(text.contains("\nbullet-list-marker = \"dash\"\n"));
+                assert!(text.contains("\nflavor = \"gfm\"\n"));
+                assert!(text.contains("\nignored-tags = [\"script\", \"style\"]\n"));
+            },
+        );
+        assert_eq!(result.exit_code, 0);
+
+        let result = exec_with_temp_fs(vec!["config", "show", "cli-options.toml"], |_| {});
+        assert_eq!(result.exit_code, 0);
+        assert!(result.stdout.contains("heading-style = \"setex\""));
+        assert!(result.stdout.contains("ul-bullet-spacing = 3"));
+
+        let result = exec_with_temp_fs(vec!["config", "validate", "cli-options.toml"], |_| {});
+        assert_eq!(result.exit_code, 0);
+
+        let result = exec_with_temp_fs(vec!["config", "validate", "merge-toc.txt"], |_| {});
+        assert_eq!(result.exit_code, 1);
+        assert!(result.stderr.contains("Invalid options file"));
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let error = |text: &str| htmd_cli::Config::from_toml(text).err().unwrap().to_string();
+
+        assert!(error("[options]\nheading_style = \"setex\"").ends_with(
+            "line 2, column 1: unknown key `heading_style`, did you mean `heading-style`?"
+        ));
+        assert!(error("[options]\nlink-styles = \"inlined\"")
+            .ends_with("unknown key `link-styles`, did you mean `link-style`?"));
+        assert!(error("[option]\nhr-style = \"dashes\"").contains("did you mean `options`?"));
+        assert!(error("[options]\nul-bullet-spacing = 300").contains("line 2, column 21:"));
+        assert!(error("[options]\nflatten-output = \"yes\"").contains("expected a boolean"));
+        assert!(error("[options]\nheading-style = \"setx\"")
+            .ends_with("expected one of `atx`, `setex`, did you mean `setex`?"));
+
+        let config = htmd_cli::Config::from_toml("[options]").unwrap();
+        assert!(config.scripting_enabled);
+
+        let result = exec_with_temp_fs(
+            vec!["hello.html", "--options-file", "merge-toc.txt"],
+            |_| {},
+        );
+        assert_eq!(result.exit_code, 1);
+        assert!(result
+            .stderr
+            .contains("Invalid options file \"merge-toc.txt\": ParseConfigError: line 2"));
+    }
+
+    #[test]
+    fn test_yaml_and_json_options_files() {
+        let dir = env::temp_dir().join(format!("htmd-options-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let yaml = dir.join("options.yaml");
+        fs::write(
+            &yaml,
+            "options:\n  heading-style: setex\n  ignored-tags: [em]\n",
+        )
+        .unwrap();
+        let json = dir.join("options.json");
+        fs::write(&json, r#"{"options": {"heading-style": "setex"}}"#).unwrap();
+        let json_txt = dir.join("options.txt");
+        fs::write(&json_txt, r#"{"options": {"heading-style": "setex"}}"#).unwrap();
+        let invalid = dir.join("invalid.yml");
+        fs::write(&invalid, "options:\n  heading-style: setx\n").unwrap();
+
+        let html = "<h1>Hello<em>!</em></h1>";
+        for args in [
+            vec!["--options-file", yaml.to_str().unwrap()],
+            vec!["--options-file", json.to_str().unwrap()],
+            vec![
+                "--options-file",
+                json_txt.to_str().unwrap(),
+                "--options-format",
+                "json",
+            ],
+        ] {
+            let result = exec_with_input(Some(html), args);
+            assert_eq!(result.exit_code, 0);
+            assert!(result.stdout.starts_with("Hello"));
+            assert!(result.stdout.contains("\n====="));
+        }
+
+        let result = exec(vec!["config", "validate", invalid.to_str().unwrap()]);
+        assert_eq!(result.exit_code, 1);
+        assert!(result
+            .stderr
+            .contains("line 2, column 3: unknown value `setx`, expected one of `atx`, `setex`"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_schema() {
+        let result = exec(vec!["config", "schema"]);
+        assert_eq!(result.exit_code, 0);
+        let schema: serde_json::Value = serde_json::from_str(&result.stdout).unwrap();
+        let options = &schema["properties"]["options"]["properties"];
+        assert_eq!(
+            options["heading-style"]["enum"],
+            serde_json::json!(["atx", "setex"])
+        );
+        assert_eq!(
+            options["code-block-style"]["enum"],
+            serde_json::json!(["fenced", "indented"])
+        );
+        assert_eq!(options["ul-bullet-spacing"]["maximum"], 255);
+        assert_eq!(options["ul-bullet-spacing"]["default"], 3);
+        assert_eq!(options["ignored-tags"]["items"]["type"], "string");
+        assert_eq!(
+            schema["properties"]["options"]["additionalProperties"],
+            false
+        );
+
+        let result = exec_with_input(
+            Some("<pre><code>a</code></pre>"),
+            vec!["--code-block-style", "indented"],
+        );
+        assert_eq!(result.stdout, "    a");
+    }
+
+    #[test]
+    fn test_check() {
+        let result = exec_with_temp_fs(vec!["check", "**/*.html"], |dir| {
+            assert_eq!(count_dir_file_count(&dir, "md", true), 0)
+        });
+        assert_eq!(result.exit_code, 0);
+        assert!(result.stdout.starts_with("Checked 4 file(s)"));
+
+        let result = exec_with_temp_fs(vec!["check", "**/*.html", "--max-nodes", "4"], |_| {});
+        assert_eq!(result.exit_code, 1);
+        assert!(result.stderr.contains("More than 4 elements (--max-nodes)"));
     }
 
     #[test]
@@ -130,6 +312,50 @@ mod tests {
             .contains("Output to stdout doesn't support multiple files as the input."))
     }
 
+    #[test]
+    fn folder_in_stdout_out_concat() {
+        let result = exec_with_temp_fs(vec!["./**/*.html", "--stdout-mode", "concat"], |_| {});
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout.matches("# Hello World!").count(), 4);
+    }
+
+    #[test]
+    fn folder_in_stdout_out_delimited() {
+        let result = exec_with_temp_fs(
+            vec![
+                "./**/*.html",
+                "--stdout-mode",
+                "delimited",
+                "--stdout-delimiter",
+                "=== {path} ===",
+            ],
+            |_| {},
+        );
+        assert_eq!(result.exit_code, 0);
+        let hello = result.stdout.find("=== hello.html ===").unwrap();
+        let hello_1 = result
+            .stdout
+            .find("=== sub-folder/hello-1.html ===")
+            .unwrap();
+        let hello_3 = result
+            .stdout
+            .find("=== sub-folder2/hello-3.html ===")
+            .unwrap();
+        assert!(hello < hello_1 && hello_1 < hello_3);
+    }
+
+    #[test]
+    fn folder_in_stdout_out_ndjson() {
+        let result = exec_with_temp_fs(vec!["./**/*.html", "--stdout-mode", "ndjson"], |_| {});
+        assert_eq!(result.exit_code, 0);
+        let lines = result.stdout.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            r#"{"path":"hello.html","markdown":"Hello!\n\n# Hello World!"}"#
+        );
+    }
+
     #[test]
     fn glob_in_folder_out_hierarchy() {
         let result = exec_with_temp_fs(vec!["**/*.html", "--output", "./"], |dir| {
@@ -156,6 +382,226 @@ mod tests {
         assert_eq!(result.exit_code, 0);
     }
 
+    #[test]
+    fn glob_in_folder_out_with_jobs() {
+        let result = exec_with_temp_fs(vec!["**/*.html", "-j", "2", "--output", "./"], |dir| {
+            let html_count = count_dir_file_count(&dir, "html", true);
+            let md_count = count_dir_file_count(&dir, "md", true);
+            assert_eq!(html_count, md_count);
+        });
+        assert_eq!(result.exit_code, 0);
+
+        let result = exec_with_temp_fs(vec!["**/*.html", "--jobs", "0", "--output", "./"], |_| {});
+        assert_eq!(result.exit_code, 2);
+    }
+
+    #[test]
+    fn glob_in_folder_out_with_verbosity() {
+        let mut html_count = 0;
+        let result = exec_with_temp_fs(vec!["**/*.html", "-vv", "--output", "converted"], |dir| {
+            html_count = count_dir_file_count(&dir, "html", true);
+        });
+        assert_eq!(result.exit_code, 0);
+        let logs = result
+            .stderr
+            .lines()
+            .filter(|line| line.starts_with("converted input="))
+            .collect::<Vec<&str>>();
+        assert_eq!(logs.len(), html_count);
+        assert!(logs.iter().all(|line| line.contains(" elapsed_ms=")));
+
+        let result = exec_with_temp_fs(vec!["**/*.html", "-q", "--output", "converted"], |_| {});
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "");
+    }
+
+    #[test]
+    fn glob_in_folder_out_with_dir_index() {
+        let result = exec_with_temp_fs(
+            // The input after `--index` is not its value
+            vec!["--output", "converted", "--index", "**/*.html"],
+            |dir| {
+                let index = fs::read_to_string(dir.join("converted").join("README.md")).unwrap();
+                assert_eq!(
+                    index,
+                    "# Index\n\n\
+                    - [Hello!](hello.md)\n\
+                    - [sub-folder](sub-folder/README.md)\n\
+                    - [sub-folder2](sub-folder2/README.md)\n"
+                );
+                let sub_index = dir.join("converted").join("sub-folder2").join("README.md");
+                assert!(fs::read_to_string(sub_index)
+                    .unwrap()
+                    .contains("- [Hello!](hello-3.md)"));
+            },
+        );
+        assert_eq!(result.exit_code, 0);
+    }
+
+    #[test]
+    fn glob_in_folder_out_with_summary_index() {
+        let result = exec_with_temp_fs(
+            vec!["**/*.html", "--output", "converted", "--index=summary"],
+            |dir| {
+                let summary = fs::read_to_string(dir.join("converted").join("SUMMARY.md")).unwrap();
+                assert!(summary.starts_with("# Summary\n\n- [Hello!](hello.md)\n"));
+                assert!(
+                    summary.contains("- [sub-folder]()\n    - [Hello!](sub-folder/hello-1.md)\n")
+                );
+            },
+        );
+        assert_eq!(result.exit_code, 0);
+    }
+
+    #[test]
+    fn glob_in_folder_out_with_hugo_layout() {
+        let result = exec_with_temp_fs(
+            vec!["**/*.html", "--output", "site", "--layout", "hugo"],
+            |dir| {
+                let page =
+                    fs::read_to_string(dir.join("site").join("sub-folder2").join("hello-3.md"))
+                        .unwrap();
+                assert!(page.starts_with("---\ntitle: \"Hello!\"\nweight: 2\n---\n\n"));
+            },
+        );
+        assert_eq!(result.exit_code, 0);
+    }
+
+    #[test]
+    fn glob_in_folder_out_with_mkdocs_layout() {
+        let result = exec_with_temp_fs(
+            vec!["**/*.html", "--output", "site", "--layout", "mkdocs"],
+            |dir| {
+                let nav = fs::read_to_string(dir.join("site").join("mkdocs-nav.yml")).unwrap();
+                assert!(
+                    nav.starts_with("nav:\n  - \"Hello!\": \"hello.md\"\n  - \"sub-folder\":\n")
+                );
+            },
+        );
+        assert_eq!(result.exit_code, 0);
+    }
+
+    #[test]
+    fn layout_links_and_summary_titles() {
+        let dir = env::temp_dir().join(format!("htmd-layout-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("guide")).unwrap();
+        fs::write(
+            dir.join("index.html"),
+            "<title>Docs</title><p><a href=\"guide/setup.html#install\">Setup</a></p>",
+        )
+        .unwrap();
+        fs::write(dir.join("guide").join("index.html"), "<title>Guide</title>").unwrap();
+        fs::write(
+            dir.join("guide").join("setup.html"),
+            "<title>Setup</title><p><a href=\"../index.html\">Home</a> \
+            <a href=\"index.html#intro\">Intro</a> <a href=\"https://a.com/b.html\">A</a></p>",
+        )
+        .unwrap();
+        let input = dir.join("**").join("*.html");
+        let site = dir.join("site");
+
+        let result = exec(vec![
+            input.to_str().unwrap(),
+            "--output",
+            site.to_str().unwrap(),
+            "--layout",
+            "mdbook",
+        ]);
+        assert_eq!(result.exit_code, 0);
+        let readme = fs::read_to_string(site.join("README.md")).unwrap();
+        assert!(readme.contains("[Setup](guide/setup.md#install)"));
+        let setup = fs::read_to_string(site.join("guide").join("setup.md")).unwrap();
+        assert!(setup
+            .contains("[Home](../README.md) [Intro](README.md#intro) [A](https://a.com/b.html)"));
+        // Chapters are named after their index page, not the directory
+        let summary = fs::read_to_string(site.join("SUMMARY.md")).unwrap();
+        assert!(summary.contains("- [Guide](guide/README.md)\n    - [Setup](guide/setup.md)\n"));
+
+        let result = exec(vec![
+            input.to_str().unwrap(),
+            "--output",
+            site.to_str().unwrap(),
+            "--layout",
+            "hugo",
+        ]);
+        assert_eq!(result.exit_code, 0);
+        let setup = fs::read_to_string(site.join("guide").join("setup.md")).unwrap();
+        assert!(setup.contains("[Home](../_index.md) [Intro](_index.md#intro)"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn glob_in_merge_out() {
+        let result = exec_with_temp_fs(vec!["**/*.html", "--merge", "merged.md"], |dir| {
+            let merged = fs::read_to_string(dir.join("merged.md")).unwrap();
+            let hello = merged
+                .find("<a id=\"hello\"></a>\n\nHello!\n\n# Hello World!")
+                .unwrap();
+            let hello_1 = merged
+                .find("<a id=\"sub-folder-hello-1\"></a>\n\nHello!\n\n## Hello World!")
+                .unwrap();
+            assert!(hello < hello_1);
+        });
+        assert_eq!(result.exit_code, 0);
+        assert!(result.stdout.contains("Merged 4 file(s)"));
+    }
+
+    #[test]
+    fn glob_in_merge_out_with_toc() {
+        let result = exec_with_temp_fs(
+            vec![
+                "**/*.html",
+                "--merge",
+                "merged.md",
+                "--merge-toc",
+                "merge-toc.txt",
+            ],
+            |dir| {
+                let merged = fs::read_to_string(dir.join("merged.md")).unwrap();
+                let hello_3 = merged.find("<a id=\"sub-folder2-hello-3\"></a>").unwrap();
+                let hello_1 = merged.find("<a id=\"sub-folder-hello-1\"></a>").unwrap();
+                let hello = merged.find("<a id=\"hello\"></a>").unwrap();
+                assert!(hello_3 < hello_1 && hello_1 < hello);
+            },
+        );
+        assert_eq!(result.exit_code, 0);
+    }
+
+    #[test]
+    fn merge_links_and_failures() {
+        let dir = env::temp_dir().join(format!("htmd-merge-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.html"),
+            "<p><a href=\"b.html#sec\">sec</a> <a href=\"b.html\">b</a></p>\
+            <pre><code class=\"language-md\">[x](b.html)\n</code></pre>",
+        )
+        .unwrap();
+        fs::write(dir.join("b.html"), "<h2 id=\"sec\">Sec</h2>").unwrap();
+        fs::write(dir.join("c.html"), format!("<p>{}</p>", "x".repeat(2048))).unwrap();
+        let merged = dir.join("merged.md");
+
+        // An oversized file fails, the others are still merged
+        let result = exec(vec![
+            dir.join("*.html").to_str().unwrap(),
+            "--merge",
+            merged.to_str().unwrap(),
+            "--max-input-size",
+            "1K",
+        ]);
+        assert_eq!(result.exit_code, 1);
+        assert!(result.stderr.contains("Failed to convert 1 file(s):"));
+        assert!(result.stderr.contains("c.html"));
+        let merged = fs::read_to_string(merged).unwrap();
+        // Fragments are kept and links in code blocks are not rewritten
+        assert!(merged.contains("[sec](#sec) [b](#b)"));
+        assert!(merged.contains("```md\n[x](b.html)\n```"));
+        assert!(!merged.contains("xxx"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
     #[test]
     fn read_options_toml() {
         let result = exec_with_temp_fs(
@@ -166,6 +612,435 @@ mod tests {
         assert!(result.stdout.contains("Hello World!\n============"))
     }
 
+    #[test]
+    fn test_env_options() {
+        let html = "<h1>Hello<em>!</em></h1><hr>";
+        let result = exec_with_env(
+            Some(html),
+            vec![],
+            vec![
+                ("HTMD_HEADING_STYLE", "setex"),
+                ("HTMD_IGNORED_TAGS", "em,hr"),
+            ],
+        );
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Hello\n=====");
+
+        // Flags take precedence over variables, variables over the options file
+        let result = exec_with_env(
+            None,
+            vec![
+                "--options-file",
+                "tests/cli-options.toml",
+                "--br-style",
+                "two-spaces",
+                "--print-config",
+            ],
+            vec![
+                ("HTMD_HR_STYLE", "dashes"),
+                ("HTMD_BR_STYLE", "backslash"),
+                ("HTMD_FLATTEN_OUTPUT", "no"),
+                ("HTMD_TABLE_PADDING", "off"),
+                ("HTMD_SCRIPTING_ENABLED", "0"),
+            ],
+        );
+        assert_eq!(result.exit_code, 0);
+        let lines = result.stdout.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "[options]");
+        assert!(
+            lines.contains(&r#"heading-style = "setex" # options file "tests/cli-options.toml""#)
+        );
+        assert!(lines.contains(&r#"hr-style = "dashes" # HTMD_HR_STYLE"#));
+        assert!(lines.contains(&r#"br-style = "two-spaces" # --br-style"#));
+        assert!(lines.contains(&"flatten-output = false # HTMD_FLATTEN_OUTPUT"));
+        assert!(lines.contains(&"table-padding = false # HTMD_TABLE_PADDING"));
+        assert!(lines.contains(&"scripting-enabled = false # HTMD_SCRIPTING_ENABLED"));
+        assert!(lines.contains(&r#"link-style = "inlined" # default"#));
+    }
+
+    #[test]
+    fn test_env_and_conflicting_flags() {
+        // A flag takes precedence over the variable of a conflicting arg
+        let result = exec_with_env(Some("<p>Hi</p>"), vec!["-q"], vec![("HTMD_VERBOSE", "1")]);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Hi");
+
+        let dir = env::temp_dir().join(format!("htmd-env-{}", uuid::Uuid::new_v4()));
+        let out = dir.join("out");
+        let merged = dir.join("merged.md");
+        let result = exec_with_env(
+            None,
+            vec!["tests/html/hello.html", "-o", out.to_str().unwrap()],
+            vec![("HTMD_MERGE", merged.to_str().unwrap())],
+        );
+        assert_eq!(result.exit_code, 0);
+        assert!(out.join("hello.md").exists());
+        assert!(!merged.exists());
+
+        // A positional input takes precedence over HTMD_INPUT
+        let env_in = dir.join("env_in.html");
+        let arg_in = dir.join("arg_in.html");
+        fs::write(&env_in, "<h1>From env</h1>").unwrap();
+        fs::write(&arg_in, "<h1>From arg</h1>").unwrap();
+        let env_input = vec![("HTMD_INPUT", env_in.to_str().unwrap())];
+        let result = exec_with_env(None, vec![arg_in.to_str().unwrap()], env_input.clone());
+        assert_eq!(result.stdout, "# From arg");
+        let result = exec_with_env(None, vec!["-i", arg_in.to_str().unwrap()], env_input);
+        assert_eq!(result.stdout, "# From arg");
+        let result = exec_with_env(
+            None,
+            vec![arg_in.to_str().unwrap()],
+            vec![("HTMD_INPUT", "")],
+        );
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "# From arg");
+        fs::remove_dir_all(dir).unwrap();
+
+        // Flags of conflicting args are still rejected
+        let result = exec_with_input(Some("<p>Hi</p>"), vec!["-q", "-v"]);
+        assert_eq!(result.exit_code, 2);
+        assert!(result
+            .stderr
+            .contains("the argument '--quiet' cannot be used with '--verbose'"));
+    }
+
+    #[test]
+    fn test_honor_meta_options() {
+        let html = r#"<head><meta name="htmd:heading-style" content="setex">
+<meta name="htmd:ignored-tags" content="aside"></head>
+<body><h1>Hello</h1><aside>Aside</aside></body>"#;
+        let result = exec_with_input(Some(html), vec![]);
+        assert_eq!(result.stdout, "# Hello\n\nAside");
+
+        let result = exec_with_input(Some(html), vec!["--honor-meta-options"]);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Hello\n=====");
+
+        let html = r#"<meta name="htmd:table-padding" content="0">
+<table><tr><th>Aaa</th></tr><tr><td>1</td></tr></table>"#;
+        let result = exec_with_input(Some(html), vec!["--flavor", "gfm", "--honor-meta-options"]);
+        assert_eq!(result.stdout, "| Aaa |\n| --- |\n| 1 |");
+
+        let html = r#"<meta name="htmd:heading-style" content="underlined"><h1>Hello</h1>"#;
+        let result = exec_with_input(Some(html), vec!["--honor-meta-options"]);
+        assert_eq!(result.exit_code, 1);
+        assert!(result.stderr.contains("Invalid meta options"));
+    }
+
+    /// A page with the syntax that differs between the Markdown flavors.
+    const FLAVOR_HTML: &str = r##"<p>Some <del>old</del> text<sup><a href="#fn1" id="fnref1">1</a></sup> and H<sub>2</sub>O.</p>
+<ul><li><input type="checkbox" checked disabled> Done</li><li><input type="checkbox" disabled> Todo</li></ul>
+<table><thead><tr><th>A</th><th>B</th></tr></thead><tbody><tr><td>1</td><td>2</td></tr></tbody></table>
+<table><tr><td>No</td><td>header</td></tr></table>
+<dl><dt>Term</dt><dd>Definition</dd><dt>Other</dt><dd><p>First</p><p>Second</p></dd></dl>
+<pre><code>code</code></pre>
+<section class="footnotes"><hr><ol><li id="fn1"><p>The note. <a href="#fnref1">↩</a></p></li></ol></section>"##;
+
+    const RAW_TABLE: &str = "<table><tbody><tr><td>No</td><td>header</td></tr></tbody></table>";
+
+    const RAW_DEFINITION_LIST: &str = "<dl><dt>Term</dt><dd>Definition</dd><dt>Other</dt>\
+        <dd><p>First</p><p>Second</p></dd></dl>";
+
+    const PIPE_TABLE: &str = "| A | B |\n| - | - |\n| 1 | 2 |";
+
+    const DEFINITION_LIST: &str = "Term\n:   Definition\n\nOther\n:   First\n\n    Second";
+
+    /// The elements of [FLAVOR_HTML] before the footnotes, which every flavor renders differently.
+    const FLAVOR_ELEMENTS: &str = "p del sup a sub ul li input li input \
+        table thead tr th th tbody tr td td table tbody tr td td dl dt dd dt dd p p pre code";
+
+    /// Render the Markdown with pulldown-cmark and list the elements in document order.
+    fn rendered_elements(md: &str, options: pulldown_cmark::Options) -> String {
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new_ext(md, options));
+        html.split('<')
+            .skip(1)
+            .map(|tag| {
+                tag.split(|c: char| !c.is_ascii_alphanumeric())
+                    .next()
+                    .unwrap_or_default()
+            })
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_flavor_commonmark() {
+        // CommonMark has no tables, strikethrough, task lists, footnotes, or definition lists,
+        // raw HTML inlines and HTML blocks keep them
+        let result = exec_with_input(Some(FLAVOR_HTML), vec!["--flavor", "commonmark"]);
+        assert_eq!(result.exit_code, 0);
+        let md = result.stdout;
+        assert!(md.starts_with("Some <del>old</del> text<sup>[1](#fn1)</sup> and H<sub>2</sub>O."));
+        assert!(md.contains("*   <input type=\"checkbox\" checked disabled> Done\n"));
+        assert!(md.contains("\n\n<table><thead><tr><th>A</th><th>B</th></tr></thead>"));
+        assert!(md.contains(&format!("\n\n{}\n\n", RAW_TABLE)));
+        assert!(md.contains(&format!("\n\n{}\n\n", RAW_DEFINITION_LIST)));
+        assert!(md.contains("\n\n```\ncode\n```\n\n"));
+        assert!(md.ends_with("1.  The note. [↩](#fnref1)"));
+
+        let elements = rendered_elements(&md, pulldown_cmark::Options::empty());
+        assert_eq!(elements, format!("{} hr ol li a", FLAVOR_ELEMENTS));
+    }
+
+    #[test]
+    fn test_flavor_gfm() {
+        let result = exec_with_input(Some(FLAVOR_HTML), vec!["--flavor", "gfm"]);
+        assert_eq!(result.exit_code, 0);
+        let md = result.stdout;
+        assert!(md.starts_with("Some ~~old~~ text[^1] and H<sub>2</sub>O."));
+        assert!(md.contains("*   [x] Done\n*   [ ] Todo"));
+        assert!(md.contains(PIPE_TABLE));
+        // A pipe table needs a header row
+        assert!(md.contains(&format!("\n\n{}\n\n", RAW_TABLE)));
+        assert!(md.contains(&format!("\n\n{}\n\n", RAW_DEFINITION_LIST)));
+        assert!(md.ends_with("\n\n[^1]: The note."));
+
+        let options = pulldown_cmark::Options::ENABLE_GFM
+            | pulldown_cmark::Options::ENABLE_TABLES
+            | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
+            | pulldown_cmark::Options::ENABLE_TASKLISTS
+            | pulldown_cmark::Options::ENABLE_FOOTNOTES;
+        let elements = rendered_elements(&md, options);
+        assert_eq!(elements, format!("{} div sup p", FLAVOR_ELEMENTS));
+    }
+
+    #[test]
+    fn test_flavor_mmd() {
+        let result = exec_with_input(Some(FLAVOR_HTML), vec!["--flavor", "mmd"]);
+        assert_eq!(result.exit_code, 0);
+        let md = result.stdout;
+        assert!(md.starts_with("Some <del>old</del> text[^1] and H<sub>2</sub>O."));
+        assert!(md.contains("*   <input type=\"checkbox\" disabled> Todo"));
+        assert!(md.contains(PIPE_TABLE));
+        assert!(md.contains(&format!("\n\n{}\n\n", DEFINITION_LIST)));
+        assert!(md.contains("\n\n```\ncode\n```\n\n"));
+        assert!(md.ends_with("\n\n[^1]: The note."));
+
+        // Definitions are rendered as paragraphs
+        let options = pulldown_cmark::Options::ENABLE_TABLES
+            | pulldown_cmark::Options::ENABLE_FOOTNOTES
+            | pulldown_cmark::Options::ENABLE_DEFINITION_LIST;
+        let elements = rendered_elements(&md, options);
+        let expected = FLAVOR_ELEMENTS.replace("dd dt", "dd p dt");
+        assert_eq!(elements, format!("{} div sup p", expected));
+    }
+
+    #[test]
+    fn test_flavor_php_extra() {
+        let result = exec_with_input(Some(FLAVOR_HTML), vec!["--flavor", "php-extra"]);
+        assert_eq!(result.exit_code, 0);
+        let md = result.stdout;
+        assert!(md.starts_with("Some <del>old</del> text[^1] and H<sub>2</sub>O."));
+        assert!(md.contains(PIPE_TABLE));
+        assert!(md.contains(&format!("\n\n{}\n\n", DEFINITION_LIST)));
+        // Markdown Extra fences code with tildes
+        assert!(md.contains("\n\n~~~\ncode\n~~~\n\n"));
+        assert!(md.ends_with("\n\n[^1]: The note."));
+
+        let options = pulldown_cmark::Options::ENABLE_TABLES
+            | pulldown_cmark::Options::ENABLE_FOOTNOTES
+            | pulldown_cmark::Options::ENABLE_DEFINITION_LIST;
+        let elements = rendered_elements(&md, options);
+        let expected = FLAVOR_ELEMENTS.replace("dd dt", "dd p dt");
+        assert_eq!(elements, format!("{} div sup p", expected));
+
+        // Options that are set take precedence over the defaults of the flavor
+        let html = "<pre><code>code</code></pre>";
+        let result = exec_with_input(
+            Some(html),
+            vec!["--flavor", "php-extra", "--code-block-fence", "backticks"],
+        );
+        assert_eq!(result.stdout, "```\ncode\n```");
+    }
+
+    #[test]
+    fn test_flavor_strict() {
+        // Markdown.pl has no fenced code blocks, code blocks are indented
+        let result = exec_with_input(Some(FLAVOR_HTML), vec!["--flavor", "strict"]);
+        assert_eq!(result.exit_code, 0);
+        let md = result.stdout;
+        assert!(md.starts_with("Some <del>old</del> text<sup>[1](#fn1)</sup> and H<sub>2</sub>O."));
+        assert!(md.contains(&format!("\n\n{}\n\n", RAW_TABLE)));
+        assert!(md.contains(&format!("\n\n{}\n\n", RAW_DEFINITION_LIST)));
+        assert!(md.contains("\n\n    code\n\n"));
+
+        let elements = rendered_elements(&md, pulldown_cmark::Options::empty());
+        assert_eq!(elements, format!("{} hr ol li a", FLAVOR_ELEMENTS));
+
+        // A blank line would end the HTML block
+        let html = "<table><tr><td><pre>a\n\nb</pre></td></tr></table>";
+        let result = exec_with_input(Some(html), vec!["--flavor", "strict"]);
+        assert_eq!(
+            result.stdout,
+            "<table><tbody><tr><td><pre>a&#10;\nb</pre></td></tr></tbody></table>"
+        );
+        let elements = rendered_elements(&result.stdout, pulldown_cmark::Options::empty());
+        assert_eq!(elements, "table tbody tr td pre");
+    }
+
+    #[test]
+    fn test_table_options() {
+        let html = r#"<table><tr><th align="left">Name</th><th style="text-align: right">Price</th></tr>
+<tr><td>A|B</td><td rowspan="2">1</td></tr>
+<tr><td>line<br>two</td></tr>
+<tr><td colspan="2">wide</td></tr></table>"#;
+        let result = exec_with_input(Some(html), vec!["--table-alignment"]);
+        assert_eq!(
+            result.stdout,
+            "| Name        | Price |\n\
+             | :---------- | ----: |\n\
+             | A\\|B        |     1 |\n\
+             | line<br>two |       |\n\
+             | wide        |       |"
+        );
+
+        let result = exec_with_input(
+            Some(html),
+            vec!["--table-span", "repeat", "--table-padding", "false"],
+        );
+        assert_eq!(
+            result.stdout,
+            "| Name | Price |\n\
+             | --- | --- |\n\
+             | A\\|B | 1 |\n\
+             | line<br>two | 1 |\n\
+             | wide | wide |"
+        );
+
+        // Cells with lists cannot be in a pipe table, they are flattened by default
+        let html = "<table><tr><th>Key</th><th>Value</th></tr>\
+            <tr><td>a</td><td><ul><li>one</li><li>two</li></ul></td></tr>\
+            <tr><td>b</td><td>plain</td></tr></table>";
+        let result = exec_with_input(Some(html), vec![]);
+        assert!(result.stdout.starts_with("| Key | Value"));
+        let result = exec_with_input(Some(html), vec!["--table-fallback", "list"]);
+        assert_eq!(
+            result.stdout,
+            "*   Key: a\n    *   Value:\n\n        *   one\n        *   two\n*   Key: b\n    *   Value: plain"
+        );
+        let result = exec_with_input(Some(html), vec!["--table-fallback", "text"]);
+        assert_eq!(
+            result.stdout,
+            "Key\n\nValue\n\na\n\n*   one\n*   two\n\nb\n\nplain"
+        );
+        let result = exec_with_input(Some(html), vec!["--table-fallback", "html"]);
+        assert!(result.stdout.starts_with("<table><tbody><tr><th>Key</th>"));
+
+        let config = htmd_cli::Config::from_toml(
+            "[options]\ntable-span = \"repeat\"\ntable-padding = false",
+        )
+        .unwrap();
+        let converter = config.into_converter();
+        let html =
+            "<table><tr><th colspan=\"2\">Both</th></tr><tr><td>1</td><td>2</td></tr></table>";
+        assert_eq!(
+            converter.convert(html).unwrap(),
+            "| Both | Both |\n| --- | --- |\n| 1 | 2 |"
+        );
+    }
+
+    #[test]
+    fn test_code_blocks() {
+        let html = r#"<pre><code class="language-rust">fn <span class="k">main</span>() {}
+</code></pre>
+<div class="highlight"><pre lang="go"><span class="nx">x</span> := <b>1</b></pre></div>
+<pre><code class="hljs highlight-python"><span class="hljs-keyword">def</span> f():<br>    pass</code></pre>
+<pre class="line-numbers"><code class="language-none">plain<span aria-hidden="true" class="line-numbers-rows"><span></span></span></code></pre>"#;
+        let result = exec_with_input(Some(html), vec![]);
+        assert_eq!(
+            result.stdout,
+            "```rust\nfn main() {}\n```\n\n\
+             ```go\nx := 1\n```\n\n\
+             ```python\ndef f():\n    pass\n```\n\n\
+             ```\nplain\n```"
+        );
+
+        let result = exec_with_input(
+            Some(html),
+            vec![
+                "--code-block-style",
+                "indented",
+                "--code-language-classes",
+                "src-*",
+            ],
+        );
+        assert!(result.stdout.starts_with("    fn main() {}\n\n    x := 1"));
+
+        // Line numbers of Pygments tables are kept with the right start
+        let html = r#"<div class="highlight-js"><table class="highlighttable"><tr>
+<td class="linenos"><div class="linenodiv"><pre>9
+10</pre></div></td>
+<td class="code"><div class="highlight"><pre>a()
+b()
+</pre></div></td></tr></table></div>"#;
+        let result = exec_with_input(Some(html), vec![]);
+        assert_eq!(result.stdout, "```js\na()\nb()\n```");
+        let result = exec_with_input(
+            Some(html),
+            vec!["--code-line-numbers", "--code-block-fence", "tildes"],
+        );
+        assert_eq!(result.stdout, "~~~js\n 9  a()\n10  b()\n~~~");
+
+        // The fence is longer than the fences in the code
+        let html = "<pre><code class=\"language-md\">```\ncode\n```</code></pre>";
+        let result = exec_with_input(Some(html), vec![]);
+        assert_eq!(result.stdout, "````md\n```\ncode\n```\n````");
+
+        // A `pre` without code or a language is left to the default handler
+        let html = r#"<pre>see <a href="x.html">link</a></pre>"#;
+        let result = exec_with_input(Some(html), vec![]);
+        assert_eq!(result.stdout, "see [link](x.html)");
+    }
+
+    #[test]
+    fn test_math() {
+        let html = r#"<p>MathJax <span class="MathJax_Preview">x</span><span class="MathJax">x</span><script type="math/tex">x^2</script> and
+<script type="math/tex; mode=display">\sum_i i</script><noscript><img src="x.png"></noscript></p>
+<p>KaTeX <span class="katex"><span class="katex-mathml"><math><semantics><mrow><mi>α</mi></mrow><annotation encoding="application/x-tex">\alpha</annotation></semantics></math></span><span class="katex-html" aria-hidden="true">α</span></span>.</p>
+<p>MathML <math><msup><mrow><mo>(</mo><mi>a</mi><mo>+</mo><mi>b</mi><mo>)</mo></mrow><mn>2</mn></msup><mo>≤</mo><mfrac><mi>π</mi><msqrt><mn>2</mn></msqrt></mfrac></math>.</p>"#;
+        // Math is not recovered by default
+        let result = exec_with_input(Some(html), vec![]);
+        assert!(result.stdout.starts_with("MathJax xx\n\nx^2\n\nand"));
+
+        let result = exec_with_input(Some(html), vec!["--math", "dollars"]);
+        assert_eq!(
+            result.stdout,
+            "MathJax $x^2$ and\n\n$$\n\\sum_i i\n$$\n\n\
+             KaTeX $\\alpha$.\n\n\
+             MathML ${(a+b)}^{2}\\leq\\frac{\\pi}{\\sqrt{2}}$."
+        );
+
+        let result = exec_with_input(Some(html), vec!["--math", "brackets"]);
+        assert!(result
+            .stdout
+            .starts_with("MathJax \\(x^2\\) and\n\n\\[\n\\sum_i i\n\\]"));
+
+        // The noscript fallback is dropped when it is parsed
+        let result = exec_with_input(
+            Some(html),
+            vec!["--math", "code", "--scripting-enabled", "false"],
+        );
+        assert!(result
+            .stdout
+            .starts_with("MathJax $`x^2`$ and\n\n```math\n\\sum_i i\n```\n\nKaTeX"));
+
+        // Ignored scripts are dropped, also math scripts
+        let result = exec_with_input(
+            Some(html),
+            vec!["--math", "code", "--ignored-tags", "script"],
+        );
+        assert!(result
+            .stdout
+            .starts_with("MathJax and\n\nKaTeX $`\\alpha`$."));
+
+        let html =
+            "<script>var x = 1;</script><math display=\"block\"><mi>sin</mi><mi>θ</mi></math>";
+        let result = exec_with_input(Some(html), vec!["--math", "dollars"]);
+        assert_eq!(result.stdout, "var x = 1;\n\n$$\n\\sin\\theta\n$$");
+    }
+
     #[test]
     fn test_ul_bullet_spacing() {
         let html = "<ul><li>Item 1</li><li>Item 2</li></ul>";
@@ -204,15 +1079,373 @@ mod tests {
         assert_eq!(result.stdout.trim(), "<https://example.com>");
     }
 
+    #[test]
+    fn test_obsidian_flavor() {
+        let html = r#"<a href="guide/Setup%20Guide.html#install">Install</a>
+            <a href="https://example.com">Example</a>
+            <img src="images/cat.png" alt="Cat">
+            <div class="admonition note"><p>Read this first.</p></div>"#;
+        let result = exec_with_input(Some(html), vec!["--flavor", "obsidian"]);
+        assert_eq!(
+            result.stdout,
+            "[[Setup Guide#install|Install]] [Example](https://example.com) ![[cat.png]]\n\n\
+            > [!note]\n\
+            > Read this first."
+        );
+    }
+
+    #[test]
+    fn test_max_input_size() {
+        let html = "<p>Hello, world!</p>";
+        let result = exec_with_input(Some(html), vec!["--max-input-size", "8"]);
+        assert_eq!(result.exit_code, 1);
+        assert!(result
+            .stderr
+            .contains("larger than --max-input-size (8 bytes)"));
+
+        let result = exec_with_input(
+            Some(html),
+            vec!["--max-input-size", "8", "--oversize", "truncate"],
+        );
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Hello");
+
+        let result = exec_with_input(Some(html), vec!["--max-input-size", "1K"]);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Hello, world!");
+
+        let max_size = u64::MAX.to_string();
+        let result = exec_with_input(Some(html), vec!["--max-input-size", &max_size]);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Hello, world!");
+    }
+
+    #[test]
+    fn test_convert_limits() {
+        let html = "<div><div><div><p>Deep</p></div></div></div>";
+        let result = exec_with_input(Some(html), vec!["--max-depth", "5"]);
+        assert_eq!(result.exit_code, 1);
+        assert!(result.stderr.contains("nested deeper than 5"));
+
+        let result = exec_with_input(Some(html), vec!["--max-depth", "6", "--max-nodes", "9"]);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Deep");
+
+        let result = exec_with_input(Some(html), vec!["--timeout-per-file", "10s"]);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.stdout, "Deep");
+
+        let result = exec_with_input(Some(html), vec!["--timeout-per-file", "soon"]);
+        assert_eq!(result.exit_code, 2);
+    }
+
+    #[test]
+    fn glob_in_folder_out_with_failures() {
+        let result = exec_with_temp_fs(
+            vec!["**/*.html", "--max-nodes", "4", "--output", "converted"],
+            |dir| assert_eq!(count_dir_file_count(&dir.join("converted"), "md", true), 0),
+        );
+        assert_eq!(result.exit_code, 1);
+        assert!(result.stdout.starts_with("Converted 0 file(s)"));
+        assert!(result.stderr.contains("Failed to convert"));
+        assert!(result.stderr.contains("More than 4 elements (--max-nodes)"));
+    }
+
+    #[test]
+    fn folder_in_with_timeout_and_stdout_failures() {
+        let dir = env::temp_dir().join(format!("htmd-timeout-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("large.html"), "<p>Paragraph</p>".repeat(200_000)).unwrap();
+        fs::write(dir.join("small.html"), "<p>Small</p>").unwrap();
+        let input = dir.join("*.html");
+
+        let result = exec(vec![
+            input.to_str().unwrap(),
+            "--timeout-per-file",
+            "1ms",
+            "--output",
+            dir.join("converted").to_str().unwrap(),
+        ]);
+        assert_eq!(result.exit_code, 1);
+        assert!(result.stderr.contains("Failed to convert 1 file(s):"));
+        assert!(result.stderr.contains("large.html\": Timed out after 1ms"));
+        assert!(dir.join("converted").join("small.md").exists());
+
+        // Stdout modes report failed files and go on with the others
+        let result = exec(vec![
+            input.to_str().unwrap(),
+            "--stdout-mode",
+            "concat",
+            "--max-input-size",
+            "1K",
+        ]);
+        assert_eq!(result.exit_code, 1);
+        assert_eq!(result.stdout, "Small");
+        assert!(result.stderr.contains("large.html\": Input"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_serve() {
+        let (mut server, addr) = spawn_server(vec!["--max-body-size", "80"]);
+
+        let response = http_request(&addr, "GET /health", None, "");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with(r#"{"status":"ok"}"#));
+
+        let response = http_request(&addr, "POST /convert", None, "<h1>Hello</h1>");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("\r\n\r\n# Hello"));
+
+        let response = http_request(
+            &addr,
+            "POST /convert?heading-style=setex&ignored-tags=em",
+            None,
+            "<h1>Hello<em>!</em></h1>",
+        );
+        assert!(response.ends_with("\r\n\r\nHello\n====="));
+
+        let body = r#"{"html":"<ul><li>A</li></ul>","options":{"bullet-list-marker":"dash"}}"#;
+        let response = http_request(&addr, "POST /convert", Some("application/json"), body);
+        assert!(response.ends_with("\r\n\r\n-   A"));
+
+        // Lists are separated by commas
+        let response = http_request(
+            &addr,
+            "POST /convert?code-language-classes=lang-*,src-*",
+            None,
+            "<pre><code class=\"src-go\">x</code></pre>",
+        );
+        assert!(response.ends_with("\r\n\r\n```go\nx\n```"));
+
+        // Booleans are read like flags
+        let response = http_request(
+            &addr,
+            "POST /convert?flavor=gfm&table-padding=no",
+            None,
+            "<table><tr><th>Aaa</th></tr><tr><td>1</td></tr></table>",
+        );
+        assert!(response.ends_with("\r\n\r\n| Aaa |\n| --- |\n| 1 |"));
+
+        let response = http_request(&addr, "POST /convert?hr-style=stars", None, "<hr>");
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        let response = http_request(&addr, "POST /convert", None, &"<p>Hi</p>".repeat(20));
+        assert!(response.starts_with("HTTP/1.1 413"));
+
+        let response = http_request(&addr, "GET /convert", None, "");
+        assert!(response.starts_with("HTTP/1.1 405"));
+
+        server.kill().unwrap();
+        server.wait().unwrap();
+
+        let max_size = u64::MAX.to_string();
+        let (mut server, addr) = spawn_server(vec!["--max-body-size", &max_size]);
+        let response = http_request(&addr, "POST /convert", None, "<h1>Hello</h1>");
+        assert!(response.ends_with("\r\n\r\n# Hello"));
+        server.kill().unwrap();
+        server.wait().unwrap();
+    }
+
+    #[test]
+    fn test_stdio_worker() {
+        let input = r#"{"id":1,"html":"<h1>Hello</h1>"}
+{"id":"b","html":"<h1>Hello</h1>","options":{"heading-style":"setex"}}
+
+{"id":3,"html":"<hr>","options":{"hr-style":"stars"}}
+{"id":4}
+not json
+{"id":5,"html":"<ul><li>A</li></ul>","options":{"heading-style":"setex"}}
+"#;
+        let result = exec_with_input(Some(input), vec!["--stdio-worker"]);
+        assert_eq!(result.exit_code, 0);
+        let lines = result.stdout.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], r##"{"id":1,"markdown":"# Hello"}"##);
+        assert_eq!(lines[1], r#"{"id":"b","markdown":"Hello\n====="}"#);
+        assert!(lines[2].starts_with(r#"{"id":3,"error":"#));
+        assert_eq!(lines[3], r#"{"id":4,"error":"\"html\" must be a string"}"#);
+        assert!(lines[4].starts_with(r#"{"id":null,"error":"Invalid JSON"#));
+        assert_eq!(lines[5], r#"{"id":5,"markdown":"*   A"}"#);
+
+        // Requests override the options and limits of the run
+        let input = r#"{"id":1,"html":"<h1>Hi</h1><hr>"}
+{"id":2,"html":"<h1>Hi</h1>","options":{"heading-style":"atx"}}
+{"id":3,"html":"<ul><li>A</li><li>B</li><li>C</li></ul>"}
+"#;
+        let result = exec_with_env(
+            Some(input),
+            vec![
+                "--stdio-worker",
+                "--options-file",
+                "tests/cli-options.toml",
+                "--max-nodes",
+                "5",
+            ],
+            vec![("HTMD_HR_STYLE", "underscores")],
+        );
+        let lines = result.stdout.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], r#"{"id":1,"markdown":"Hi\n==\n\n_ _ _"}"#);
+        assert_eq!(lines[1], r##"{"id":2,"markdown":"# Hi"}"##);
+        assert_eq!(
+            lines[2],
+            r#"{"id":3,"error":"More than 5 elements (--max-nodes)"}"#
+        );
+    }
+
+    #[test]
+    fn test_mcp() {
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05"}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"convert_html","arguments":{"html":"<h1>Hi</h1>","options":{"heading-style":"setex"}}}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"convert_file","arguments":{"path":"sub-folder/hello-1.html"}}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"convert_file","arguments":{"path":"../../mod.rs"}}}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"tools/call","params":{"name":"convert_directory","arguments":{"path":".","recursive":false}}}"#,
+            r#"{"jsonrpc":"2.0","id":7,"method":"resources/list"}"#,
+        ]
+        .join("\n");
+        let result = exec_with_temp_fs_and_input(Some(&input), vec!["mcp"], |_| {});
+        assert_eq!(result.exit_code, 0);
+
+        let responses = result
+            .stdout
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(responses.len(), 7);
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "htmd");
+
+        let tools = responses[1]["result"]["tools"].as_array().unwrap();
+        let names = tools
+            .iter()
+            .map(|tool| tool["name"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["convert_html", "convert_file", "convert_directory"]);
+        let options = &tools[0]["inputSchema"]["properties"]["options"]["properties"];
+        assert_eq!(
+            options["heading-style"]["enum"],
+            serde_json::json!(["atx", "setex"])
+        );
+
+        assert_eq!(responses[2]["result"]["content"][0]["text"], "Hi\n==");
+        assert_eq!(responses[3]["result"]["isError"], false);
+        assert_eq!(responses[4]["result"]["isError"], true);
+
+        let texts = responses[5]["result"]["content"].as_array().unwrap();
+        assert_eq!(texts.len(), 1);
+        assert!(texts[0]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("<!-- hello.html -->"));
+
+        assert_eq!(responses[6]["error"]["code"], -32601);
+    }
+
+    #[test]
+    fn test_library_plan_and_convert() {
+        let config = htmd_cli::Config::from_toml("[options]\nflatten-output = true").unwrap();
+        let batch = htmd_cli::BatchConverter::new(config).jobs(2);
+        let files = vec![
+            PathBuf::from("pages/index.html"),
+            PathBuf::from("pages/guide/setup.html"),
+        ];
+        let plan = batch.plan(&files, Path::new("out"));
+        let outputs = plan
+            .iter()
+            .map(|entry| entry.output.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            [Path::new("out/index.md"), Path::new("out/setup.md")]
+        );
+
+        exec_with_temp_fs(vec!["--version"], |dir| {
+            let files = vec![dir.join("hello.html"), dir.join("missing.html")];
+            let result = htmd_cli::BatchConverter::new(htmd_cli::Config::default())
+                .convert(&files, &dir.join("converted"))
+                .unwrap();
+            assert_eq!(result.pages.len(), 1);
+            assert_eq!(
+                result.pages[0].output,
+                dir.join("converted").join("hello.md")
+            );
+            assert_eq!(result.failures.len(), 1);
+            assert_eq!(result.failures[0].file, dir.join("missing.html"));
+        });
+    }
+
+    fn spawn_server(args: Vec<&str>) -> (Child, String) {
+        let mut child = Command::new("cargo")
+            .arg("run")
+            .arg("--")
+            .arg("serve")
+            .arg("--listen")
+            .arg("127.0.0.1:0")
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to spawn child process");
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut line)
+            .expect("Failed to read stdout");
+        let addr = line
+            .trim()
+            .strip_prefix("Listening on http://")
+            .unwrap_or_else(|| panic!("Unexpected output: {}", line))
+            .to_string();
+        (child, addr)
+    }
+
+    fn http_request(
+        addr: &str,
+        request_line: &str,
+        content_type: Option<&str>,
+        body: &str,
+    ) -> String {
+        let mut stream = TcpStream::connect(addr).expect("Failed to connect");
+        let content_type = content_type
+            .map(|content_type| format!("Content-Type: {}\r\n", content_type))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+            request_line,
+            addr,
+            content_type,
+            body.len(),
+            body
+        )
+        .expect("Failed to send request");
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("Failed to read response");
+        response
+    }
+
     fn exec(args: Vec<&str>) -> ExecResult {
         exec_with_input(None, args)
     }
 
     fn exec_with_input(input_text: Option<&str>, args: Vec<&str>) -> ExecResult {
+        exec_with_env(input_text, args, vec![])
+    }
+
+    fn exec_with_env(
+        input_text: Option<&str>,
+        args: Vec<&str>,
+        envs: Vec<(&str, &str)>,
+    ) -> ExecResult {
         let mut child = Command::new("cargo")
             .arg("run")
             .arg("--")
             .args(args)
+            .envs(envs)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -236,26 +1469,32 @@ mod tests {
         }
     }
 
-    fn exec_with_temp_fs(args: Vec<&str>, verify: impl FnOnce(PathBuf) -> ()) -> ExecResult {
+    fn exec_with_temp_fs(args: Vec<&str>, verify: impl FnOnce(PathBuf)) -> ExecResult {
         exec_with_temp_fs_and_input(None, args, verify)
     }
 
     fn exec_with_temp_fs_and_input(
         input_text: Option<&str>,
         args: Vec<&str>,
-        verify: impl FnOnce(PathBuf) -> (),
+        verify: impl FnOnce(PathBuf),
     ) -> ExecResult {
         let tests_dir = env::current_dir().unwrap().join("tests");
         let html_dir = tests_dir.join("html");
         let temp_dir = tests_dir
             .join("temp")
-            .join(format!("{}", uuid::Uuid::new_v4().to_string()));
+            .join(format!("{}", uuid::Uuid::new_v4()));
+        let _temp_dir_guard = TempDir(temp_dir.clone());
         // Copy html dir temp dir
         copy_dir(&html_dir, &temp_dir)
-            .expect(format!("Cannot setup temp dir: {:?}", temp_dir).as_str());
+            .unwrap_or_else(|_| panic!("Cannot setup temp dir: {:?}", temp_dir));
         fs::copy(
             tests_dir.join("cli-options.toml"),
-            &temp_dir.join("cli-options.toml"),
+            temp_dir.join("cli-options.toml"),
+        )
+        .unwrap();
+        fs::copy(
+            tests_dir.join("merge-toc.txt"),
+            temp_dir.join("merge-toc.txt"),
         )
         .unwrap();
 
@@ -288,12 +1527,21 @@ mod tests {
 
         verify(temp_dir.clone());
 
-        fs::remove_dir_all(temp_dir.clone())
-            .expect(format!("Cannot delete temp dir: {}", temp_dir.to_str().unwrap()).as_str());
-
         result
     }
 
+    /// Deletes a temp dir when dropped, also when a test fails in the middle.
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            if self.0.exists() {
+                fs::remove_dir_all(&self.0)
+                    .unwrap_or_else(|_| eprintln!("Cannot delete temp dir: {:?}", self.0));
+            }
+        }
+    }
+
     fn copy_dir(source: &Path, target: &Path) -> io::Result<()> {
         if !target.exists() {
             fs::create_dir_all(target)?;
@@ -318,17 +1566,15 @@ mod tests {
 
     fn count_dir_file_count(dir: &PathBuf, ext: &str, recursively: bool) -> usize {
         let mut count: usize = 0;
-        for entry in fs::read_dir(dir).unwrap() {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_dir() {
-                    if recursively {
-                        count += count_dir_file_count(&path, ext, true);
-                    }
-                } else {
-                    if path.extension().unwrap().to_str().unwrap() == ext {
-                        count += 1;
-                    }
+        for entry in fs::read_dir(dir).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if recursively {
+                    count += count_dir_file_count(&path, ext, true);
+                }
+            } else {
+                if path.extension().unwrap().to_str().unwrap() == ext {
+                    count += 1;
                 }
             }
         }